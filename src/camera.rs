@@ -1,6 +1,15 @@
-use crate::math::{Float3, Float4x4};
+use crate::math::{Float2, Float3, Float4, Float4x4};
 use crate::transform::Transform;
 
+/// Kind of projection used by a camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectionType {
+    /// Objects further away from the camera appear smaller
+    Perspective,
+    /// Parallel projection where the size of objects does not depend on their distance
+    Orthographic,
+}
+
 /// A virtual camera
 #[derive(Debug, Clone, Copy)]
 pub struct Camera {
@@ -23,6 +32,8 @@ pub struct Camera {
     pub transform: Transform,
     /// Projection matrix of the camera
     pub projection: Float4x4,
+    /// Kind of projection described by the projection matrix
+    pub projection_type: ProjectionType,
 }
 
 impl Camera {
@@ -56,6 +67,7 @@ impl Camera {
             left,
             transform: Transform::from_vectors(target, up, position, Float3::ones()),
             projection,
+            projection_type: ProjectionType::Perspective,
         }
     }
 
@@ -90,6 +102,117 @@ impl Camera {
             left,
             transform: Transform::from_vectors(target, up, position, Float3::ones()),
             projection,
+            projection_type: ProjectionType::Perspective,
+        }
+    }
+
+    /// Create a new camera with orthographic projection that looks at target and has an
+    /// up-vector. The viewing volume is a box of the given width and height centered
+    /// around the viewing direction.
+    pub fn orthographic(
+        position: Float3,
+        target: Float3,
+        up: Float3,
+        width: f32,
+        height: f32,
+        near: f32,
+        far: f32,
+    ) -> Self {
+        // Orthographic projection
+        // From view space to normalized device coordinates
+        let top = height / 2.0;
+        let bottom = -height / 2.0;
+        let right = width / 2.0;
+        let left = -width / 2.0;
+
+        let projection = Float4x4::orthographic_projection(near, far, left, right, top, bottom);
+
+        Self {
+            aspect_ratio: width / height,
+            near,
+            far,
+            top,
+            bottom,
+            right,
+            left,
+            transform: Transform::from_vectors(target, up, position, Float3::ones()),
+            projection,
+            projection_type: ProjectionType::Orthographic,
         }
     }
+
+    /// Homogeneous matrix describing the transformation from world space to the camera's
+    /// projected view space
+    pub fn view_proj_matrix(&self) -> Float4x4 {
+        self.projection * self.transform.inverse_world_matrix()
+    }
+
+    /// Reverse the projection of a depth in [0, 1] to the z coordinate in view space
+    ///
+    /// The result lies in [far, near] (note that far and near are negative).
+    pub fn linear_depth(&self, depth: f32) -> f32 {
+        let (near, far) = (self.near, self.far);
+        match self.projection_type {
+            ProjectionType::Perspective => {
+                2.0 * far * near / (far + near - (2.0 * depth - 1.0) * (far - near))
+            }
+            ProjectionType::Orthographic => near + depth * (far - near),
+        }
+    }
+
+    /// Reverse the projection of a depth in [0, 1] to a depth in view space which is
+    /// linearly mapped to [0, 1] between the near and far plane
+    pub fn normalized_linear_depth(&self, depth: f32) -> f32 {
+        (self.linear_depth(depth) - self.near) / (self.far - self.near)
+    }
+
+    /// Direction in world space of the ray through a point on the screen
+    ///
+    /// The point is given in normalized coordinates in [0, 1] x [0, 1], where (0, 0) is
+    /// the top left corner of the screen. All rays of an orthographic camera are
+    /// parallel to its viewing direction.
+    pub fn view_direction(&self, screen: Float2) -> Float3 {
+        let direction = match self.projection_type {
+            ProjectionType::Perspective => Float3::new(
+                self.left + screen.x * (self.right - self.left),
+                self.top + screen.y * (self.bottom - self.top),
+                self.near,
+            ),
+            ProjectionType::Orthographic => Float3::new(0.0, 0.0, -1.0),
+        };
+
+        (self.transform.get_inverse_rotation() * Float4::from_vector(direction))
+            .xyz()
+            .normalized()
+    }
+
+    /// Corners of the part of the viewing volume between two depths (negative z
+    /// coordinates in view space) in world space
+    ///
+    /// The first four corners lie at `near`, the last four at `far`.
+    pub fn frustum_corners(&self, near: f32, far: f32) -> [Float3; 8] {
+        let world_matrix = self.transform.world_matrix();
+        let mut corners = [Float3::zeros(); 8];
+        for (i, z) in [near, far].into_iter().enumerate() {
+            // Perspective projection widens the viewing volume with distance
+            let scale = match self.projection_type {
+                ProjectionType::Perspective => z / self.near,
+                ProjectionType::Orthographic => 1.0,
+            };
+            for (j, (x, y)) in [
+                (self.left, self.bottom),
+                (self.right, self.bottom),
+                (self.right, self.top),
+                (self.left, self.top),
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let corner = Float4::from_point(Float3::new(x * scale, y * scale, z));
+                corners[4 * i + j] = (world_matrix * corner).xyz();
+            }
+        }
+
+        corners
+    }
 }
@@ -9,3 +9,5 @@ pub mod camera;
 pub mod texture;
 pub mod shader;
 pub mod light;
+pub mod material;
+pub mod postprocess;
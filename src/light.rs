@@ -1,6 +1,133 @@
 use crate::camera::Camera;
-use crate::math::Float3;
-use crate::texture::Texture;
+use crate::math::{Float2, Float3, Float4, Float4x4};
+use crate::shader::distribution_ggx;
+use crate::texture::{CubeMap, MipmapFilter, Texture};
+use crate::transform::Transform;
+use std::sync::{Arc, RwLock};
+
+/// Arrangement of the taps used to filter shadow map lookups
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowKernel {
+    /// Fixed Poisson-disk distributed taps (at most 16)
+    PoissonDisk,
+    /// Regular grid of taps which is rotated to avoid aligning with texel rows
+    ///
+    /// The number of taps is rounded up to a square number, such that the grid is
+    /// complete and centered.
+    RotatedGrid,
+}
+
+/// Filtering applied when looking up a shadow map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadowFilter {
+    /// Single lookup producing hard shadows
+    Hard,
+    /// Percentage-closer filtering (PCF) with a fixed kernel
+    ///
+    /// The fraction of taps within `radius` (in texels) which are not occluded
+    /// determines the amount of light.
+    Pcf {
+        /// Arrangement of the taps
+        kernel: ShadowKernel,
+        /// Number of taps
+        taps: usize,
+        /// Radius of the kernel in texels
+        radius: f32,
+    },
+    /// Percentage-closer soft shadows (PCSS)
+    ///
+    /// The average distance of blockers is estimated from the shadow map and the radius
+    /// of the PCF kernel grows with the size of the penumbra. Shadows are therefore hard
+    /// close to their casters and become softer with distance.
+    Pcss {
+        /// Arrangement of the taps used for the blocker search and filtering
+        kernel: ShadowKernel,
+        /// Number of taps
+        taps: usize,
+        /// Size of the light in world units
+        ///
+        /// For lights with orthographic shadow cameras, this is the tangent of the
+        /// angular size of the light instead.
+        light_size: f32,
+    },
+    /// Variance shadow maps (VSM)
+    ///
+    /// The shadow pass stores the first two moments of the depth distribution which are
+    /// blurred and bound the fraction of occluders with Chebyshev's inequality.
+    Variance {
+        /// Radius of the Gaussian blur over the moments in texels (0 disables blurring)
+        blur_radius: usize,
+        /// Lower bound of the variance avoiding numerical problems on flat surfaces
+        min_variance: f32,
+        /// Fraction of the upper bound below which a fragment is considered to be fully
+        /// in shadow, reducing light bleeding where several occluders overlap
+        light_bleeding_reduction: f32,
+    },
+    /// Exponential shadow maps (ESM)
+    ///
+    /// The shadow pass stores the exponential of the depth which is blurred and compared
+    /// to the exponential of the receiver's depth.
+    Exponential {
+        /// Radius of the Gaussian blur over the exponential depths in texels (0 disables
+        /// blurring)
+        blur_radius: usize,
+        /// Sharpness of the transition between light and shadow (at most 88 to avoid
+        /// overflows)
+        exponent: f32,
+    },
+}
+
+/// Offsets avoiding self-shadowing (shadow acne) of surfaces
+///
+/// Too large offsets detach shadows from their casters (peter panning).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowBias {
+    /// Depth offset in [0, 1] depth units added to all depths of the shadow map
+    pub constant: f32,
+    /// Factor of the depth slope of a triangle in the shadow map (the largest change of
+    /// depth between neighboring texels) which is added to its depths
+    pub slope_scale: f32,
+    /// Distance in world units by which a position is moved along its normal before
+    /// looking it up in the shadow map
+    pub normal_offset: f32,
+}
+
+impl ShadowBias {
+    /// Create new shadow bias settings
+    pub fn new(constant: f32, slope_scale: f32, normal_offset: f32) -> Self {
+        Self {
+            constant,
+            slope_scale,
+            normal_offset,
+        }
+    }
+}
+
+impl Default for ShadowBias {
+    fn default() -> Self {
+        Self::new(0.0005, 2.0, 0.05)
+    }
+}
+
+impl ShadowFilter {
+    /// Whether the filter requires the moments of the shadow map depths
+    pub fn uses_moments(&self) -> bool {
+        matches!(
+            self,
+            ShadowFilter::Variance { .. } | ShadowFilter::Exponential { .. }
+        )
+    }
+}
+
+impl Default for ShadowFilter {
+    fn default() -> Self {
+        ShadowFilter::Pcf {
+            kernel: ShadowKernel::PoissonDisk,
+            taps: 16,
+            radius: 1.5,
+        }
+    }
+}
 
 /// A cone-like spotlight
 pub struct SpotLight {
@@ -16,6 +143,21 @@ pub struct SpotLight {
     pub camera: Camera,
     /// Shadow map as a depth texture
     pub shadow_map: Texture<f32>,
+    /// Transformation from world space to the camera's projected view space when the
+    /// shadow map was last rendered
+    pub shadow_matrix: Float4x4,
+    /// Moments of the shadow map depths, only used by
+    /// [variance and exponential shadow maps](ShadowFilter::uses_moments)
+    pub shadow_moments: Texture<Float2>,
+    /// Number of samples per texel used when rendering the shadow map
+    ///
+    /// Must be one of 1, 2, 4, or 8. With more than one sample, a texel stores the
+    /// nearest depth of all its samples.
+    pub shadow_map_samples: usize,
+    /// Filtering of shadow map lookups
+    pub shadow_filter: ShadowFilter,
+    /// Offsets avoiding shadow acne
+    pub shadow_bias: ShadowBias,
 }
 
 impl SpotLight {
@@ -28,21 +170,618 @@ impl SpotLight {
         shadow_map_width: usize,
         shadow_map_height: usize,
     ) -> Self {
+        let camera = Camera::new(
+            position,
+            target,
+            Float3::unit_y(),
+            2.0 * angle,
+            (shadow_map_width as f32) / (shadow_map_height as f32),
+            -1.0,
+            -100.0,
+        );
+
         Self {
             color,
             position,
             target,
             angle,
-            camera: Camera::new(
+            camera,
+            shadow_map: Texture::new(shadow_map_width, shadow_map_height),
+            shadow_matrix: camera.view_proj_matrix(),
+            shadow_moments: Texture::new(0, 0),
+            shadow_map_samples: 1,
+            shadow_filter: ShadowFilter::default(),
+            shadow_bias: ShadowBias::default(),
+        }
+    }
+
+    /// Homogeneous matrix describing the transformation from world space to the
+    /// spotlight's projected view space
+    pub fn view_proj_matrix(&self) -> Float4x4 {
+        self.camera.view_proj_matrix()
+    }
+}
+
+/// One part of a cascaded shadow map covering a range of view depths
+pub struct ShadowCascade {
+    /// Camera used for producing the shadow map (uses orthographic projection)
+    pub camera: Camera,
+    /// Shadow map as a depth texture
+    pub shadow_map: Texture<f32>,
+    /// Transformation from world space to the camera's projected view space when the
+    /// shadow map was last rendered
+    pub shadow_matrix: Float4x4,
+    /// Moments of the shadow map depths, only used by
+    /// [variance and exponential shadow maps](ShadowFilter::uses_moments)
+    pub shadow_moments: Texture<Float2>,
+    /// Start of the covered range of depths along the negative z-axis in the view space
+    /// of the camera the cascades were fitted to
+    pub near: f32,
+    /// End of the covered range of depths along the negative z-axis in the view space of
+    /// the camera the cascades were fitted to (observe that `far < near`)
+    pub far: f32,
+}
+
+/// A light infinitely far away whose rays are parallel, like sunlight
+///
+/// Shadows are computed with cascaded shadow maps: the view frustum of a camera is split
+/// into ranges of depth and each range gets its own shadow map with an orthographic
+/// camera fitted to it. Close to the camera, shadows are thereby sharper while the
+/// shadow maps still cover large distances.
+pub struct DirectionalLight {
+    /// Color of the emitted light
+    pub color: Float3,
+    /// Direction in which the light travels
+    pub direction: Float3,
+    /// Shadow map cascades ordered from near to far
+    ///
+    /// Have to be fitted to the [view frustum](DirectionalLight::fit_to_frustum) or the
+    /// [scene bounds](DirectionalLight::fit_to_bounds).
+    pub cascades: Vec<ShadowCascade>,
+    /// Blend between a uniform (0) and a logarithmic (1) split of the view frustum
+    /// into cascades
+    pub split_lambda: f32,
+    /// Fraction of a cascade's depth range at its far end in which its shadow is blended
+    /// with the next cascade (0 disables blending)
+    pub cascade_blend: f32,
+    /// Homogeneous matrix describing the transformation from world space to the view
+    /// space of the camera the cascades were fitted to
+    pub view_matrix: Float4x4,
+    /// Number of samples per texel used when rendering the shadow maps
+    ///
+    /// Must be one of 1, 2, 4, or 8. With more than one sample, a texel stores the
+    /// nearest depth of all its samples.
+    pub shadow_map_samples: usize,
+    /// Filtering of shadow map lookups
+    pub shadow_filter: ShadowFilter,
+    /// Offsets avoiding shadow acne
+    pub shadow_bias: ShadowBias,
+}
+
+impl DirectionalLight {
+    /// Create a new directional light with a number of shadow map cascades.
+    ///
+    /// The cascades initially cover a unit box around the origin.
+    pub fn new(
+        color: Float3,
+        direction: Float3,
+        cascade_count: usize,
+        shadow_map_width: usize,
+        shadow_map_height: usize,
+    ) -> Self {
+        let direction = direction.normalized();
+        let unit_box = box_corners(-0.5 * Float3::ones(), 0.5 * Float3::ones());
+        let cascades = (0..cascade_count)
+            .map(|_| {
+                let camera = fit_orthographic_camera(direction, &unit_box, &unit_box);
+                ShadowCascade {
+                    camera,
+                    shadow_map: Texture::new(shadow_map_width, shadow_map_height),
+                    shadow_matrix: camera.view_proj_matrix(),
+                    shadow_moments: Texture::new(0, 0),
+                    near: f32::INFINITY,
+                    far: f32::NEG_INFINITY,
+                }
+            })
+            .collect();
+
+        Self {
+            color,
+            direction,
+            cascades,
+            split_lambda: 0.75,
+            cascade_blend: 0.1,
+            view_matrix: Float4x4::eye(),
+            shadow_map_samples: 1,
+            shadow_filter: ShadowFilter::default(),
+            shadow_bias: ShadowBias::default(),
+        }
+    }
+
+    /// Fit the shadow cameras to an axis-aligned box in world space, e.g. the bounds of
+    /// the scene
+    ///
+    /// All cascades cover the whole box, so only the first one is
+    /// [used](DirectionalLight::active_cascades).
+    pub fn fit_to_bounds(&mut self, min: Float3, max: Float3) {
+        let corners = box_corners(min, max);
+        let camera = fit_orthographic_camera(self.direction, &corners, &corners);
+        for cascade in self.cascades.iter_mut() {
+            cascade.camera = camera;
+            cascade.near = f32::INFINITY;
+            cascade.far = f32::NEG_INFINITY;
+        }
+        self.view_matrix = Float4x4::eye();
+    }
+
+    /// Split the viewing volume of a camera into the cascades and fit their shadow
+    /// cameras to it
+    ///
+    /// Shadow casters outside of the viewing volume can still cast shadows into it, so
+    /// the shadow cameras are extended towards the light to include the axis-aligned box
+    /// between `scene_min` and `scene_max`.
+    pub fn fit_to_frustum(&mut self, camera: &Camera, scene_min: Float3, scene_max: Float3) {
+        let casters = box_corners(scene_min, scene_max);
+        // Split distances are computed with positive distances from the camera
+        let (near, far) = (-camera.near, -camera.far);
+        let count = self.cascades.len() as f32;
+
+        let mut split_near = camera.near;
+        for (i, cascade) in self.cascades.iter_mut().enumerate() {
+            let t = (i + 1) as f32 / count;
+            let logarithmic = near * (far / near).powf(t);
+            let uniform = near + (far - near) * t;
+            let split_far =
+                -(self.split_lambda * logarithmic + (1.0 - self.split_lambda) * uniform);
+
+            // The blend region of the previous cascade is also covered by this one
+            let receivers = camera.frustum_corners(split_near, split_far);
+            cascade.camera = fit_orthographic_camera(self.direction, &receivers, &casters);
+            cascade.near = split_near;
+            cascade.far = split_far;
+
+            split_near = split_far + self.cascade_blend * (split_near - split_far);
+        }
+        self.view_matrix = camera.transform.inverse_world_matrix();
+    }
+
+    /// Number of cascades in use, starting with the first one
+    ///
+    /// After [fit_to_bounds](DirectionalLight::fit_to_bounds) the first cascade covers
+    /// all depths, so the following cascades are neither rendered nor sampled.
+    pub fn active_cascades(&self) -> usize {
+        self.cascades
+            .iter()
+            .position(|cascade| cascade.far == f32::NEG_INFINITY)
+            .map_or(self.cascades.len(), |i| i + 1)
+    }
+
+    /// Select the cascade for a position in world space
+    ///
+    /// Returns the index of the cascade and the weight with which the next cascade is
+    /// blended in, or `None` if the position lies beyond the last cascade.
+    pub fn select_cascade(&self, position: Float3) -> Option<(usize, f32)> {
+        let z = (self.view_matrix * Float4::from_point(position)).z;
+        let i = self.cascades.iter().position(|cascade| z >= cascade.far)?;
+
+        let cascade = &self.cascades[i];
+        let blend_start = cascade.far + self.cascade_blend * (cascade.near - cascade.far);
+        let weight = if i + 1 < self.cascades.len() && z < blend_start && cascade.far.is_finite() {
+            (blend_start - z) / (blend_start - cascade.far)
+        } else {
+            0.0
+        };
+
+        Some((i, weight))
+    }
+}
+
+/// Fit an orthographic camera looking in a direction tightly around the receivers
+/// (points in world space) and move its near plane towards the light such that all
+/// casters are included
+fn fit_orthographic_camera(direction: Float3, receivers: &[Float3], casters: &[Float3]) -> Camera {
+    // Avoid an up-vector parallel to the direction of the light
+    let up = if direction.y.abs() > 0.99 {
+        Float3::unit_z()
+    } else {
+        Float3::unit_y()
+    };
+
+    // Bounds in the light's view space (looking down the negative z-axis)
+    let orientation = Transform::from_vectors(direction, up, Float3::zeros(), Float3::ones());
+    let to_light_space = orientation.inverse_world_matrix();
+    let (mut min, mut max) = (
+        Float3::ones() * f32::INFINITY,
+        Float3::ones() * f32::NEG_INFINITY,
+    );
+    for p in receivers.iter() {
+        let p = (to_light_space * Float4::from_point(*p)).xyz();
+        min = Float3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+        max = Float3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+    }
+    for p in casters.iter() {
+        max.z = max.z.max((to_light_space * Float4::from_point(*p)).z);
+    }
+
+    // Place the camera in front of the closest point with the near plane at distance 1
+    // and leave some room behind the furthest point
+    let center = Float4::from_point(Float3::new(
+        0.5 * (min.x + max.x),
+        0.5 * (min.y + max.y),
+        max.z + 1.0,
+    ));
+    let position = (orientation.world_matrix() * center).xyz();
+    let width = (max.x - min.x).max(f32::EPSILON);
+    let height = (max.y - min.y).max(f32::EPSILON);
+
+    Camera::orthographic(
+        position,
+        position + direction,
+        up,
+        width,
+        height,
+        -1.0,
+        -(max.z - min.z + 2.0),
+    )
+}
+
+/// A light emitting in all directions from a single point, like a light bulb
+pub struct PointLight {
+    /// Color of the emitted light
+    pub color: Float3,
+    /// Position of the light in world space
+    pub position: Float3,
+    /// Constant, linear, and quadratic coefficients of the distance attenuation
+    pub attenuation: Float3,
+    /// Cameras used for producing the faces of the cube shadow map (use perspective
+    /// projection with a field-of-view of 90°)
+    ///
+    /// The faces look along the +x, -x, +y, -y, +z, and -z axis, see
+    /// [face](PointLight::face).
+    pub cameras: [Camera; 6],
+    /// Faces of the cube shadow map as depth textures
+    pub shadow_maps: [Texture<f32>; 6],
+    /// Transformations from world space to the projected view spaces of the cameras
+    /// when the shadow map faces were last rendered
+    pub shadow_matrices: [Float4x4; 6],
+    /// Moments of the depths of the cube shadow map faces, only used by
+    /// [variance and exponential shadow maps](ShadowFilter::uses_moments)
+    pub shadow_moments: [Texture<Float2>; 6],
+    /// Number of samples per texel used when rendering the shadow maps
+    ///
+    /// Must be one of 1, 2, 4, or 8. With more than one sample, a texel stores the
+    /// nearest depth of all its samples.
+    pub shadow_map_samples: usize,
+    /// Filtering of shadow map lookups
+    pub shadow_filter: ShadowFilter,
+    /// Offsets avoiding shadow acne
+    pub shadow_bias: ShadowBias,
+}
+
+impl PointLight {
+    /// Create a new point light with quadratic shadow map faces.
+    pub fn new(
+        color: Float3,
+        position: Float3,
+        attenuation: Float3,
+        shadow_map_size: usize,
+    ) -> Self {
+        let axes = [
+            Float3::unit_x(),
+            -Float3::unit_x(),
+            Float3::unit_y(),
+            -Float3::unit_y(),
+            Float3::unit_z(),
+            -Float3::unit_z(),
+        ];
+        let cameras = axes.map(|axis| {
+            // Avoid an up-vector parallel to the viewing direction
+            let up = if axis.y == 0.0 {
+                Float3::unit_y()
+            } else {
+                Float3::unit_z()
+            };
+            Camera::new(
                 position,
-                target,
-                Float3::unit_y(),
-                2.0 * angle,
-                (shadow_map_width as f32) / (shadow_map_height as f32),
-                -1.0,
+                position + axis,
+                up,
+                90f32.to_radians(),
+                1.0,
+                -0.1,
                 -100.0,
-            ),
-            shadow_map: Texture::new(shadow_map_width, shadow_map_height),
+            )
+        });
+
+        Self {
+            color,
+            position,
+            attenuation,
+            cameras,
+            shadow_maps: std::array::from_fn(|_| Texture::new(shadow_map_size, shadow_map_size)),
+            shadow_matrices: cameras.map(|camera| camera.view_proj_matrix()),
+            shadow_moments: std::array::from_fn(|_| Texture::new(0, 0)),
+            shadow_map_samples: 1,
+            shadow_filter: ShadowFilter::default(),
+            shadow_bias: ShadowBias::default(),
+        }
+    }
+
+    /// Fraction of the light's intensity remaining at a distance
+    pub fn attenuation(&self, distance: f32) -> f32 {
+        1.0 / (self.attenuation.x
+            + self.attenuation.y * distance
+            + self.attenuation.z * distance * distance)
+    }
+
+    /// Index of the cube shadow map face containing a direction pointing away from the
+    /// light
+    ///
+    /// The face is chosen by the major axis of the direction.
+    pub fn face(&self, direction: Float3) -> usize {
+        let abs = Float3::new(direction.x.abs(), direction.y.abs(), direction.z.abs());
+        if abs.x >= abs.y && abs.x >= abs.z {
+            if direction.x >= 0.0 { 0 } else { 1 }
+        } else if abs.y >= abs.z {
+            if direction.y >= 0.0 { 2 } else { 3 }
+        } else if direction.z >= 0.0 {
+            4
+        } else {
+            5
+        }
+    }
+}
+
+/// Corners of an axis-aligned box
+fn box_corners(min: Float3, max: Float3) -> [Float3; 8] {
+    let mut corners = [Float3::zeros(); 8];
+    for (i, corner) in corners.iter_mut().enumerate() {
+        *corner = Float3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        );
+    }
+    corners
+}
+
+/// Number of samples of the GGX distribution per texel of a prefiltered specular level
+const PREFILTER_SAMPLES: u32 = 64;
+
+/// Light arriving from all directions of a distant environment, like the sky
+///
+/// The environment is given as a cube map of radiance. Since it is infinitely far away,
+/// only the direction matters and the lighting is precomputed: the diffuse irradiance
+/// is stored as spherical harmonics and the specular reflection as cube maps of the
+/// radiance prefiltered with the GGX distribution for increasing roughness
+/// (split-sum approximation). Shadows of models are not taken into account.
+#[derive(Debug, Clone)]
+pub struct EnvironmentLight {
+    /// Scale of the environment's radiance
+    pub intensity: f32,
+    /// Coefficients of the first three bands of spherical harmonics describing the
+    /// irradiance divided by π
+    pub irradiance: [Float3; 9],
+    /// Prefiltered radiance for roughness values evenly spaced in [0, 1]
+    pub specular: Vec<CubeMap<Float3>>,
+}
+
+impl EnvironmentLight {
+    /// Create a new environment light from a cube map of radiance
+    ///
+    /// There are `specular_levels` prefiltered levels. The first one, for perfect
+    /// mirrors, has faces of `specular_size` x `specular_size` texels and every
+    /// following level halves the size, as rougher reflections are blurrier.
+    pub fn new(
+        environment: &CubeMap<Float3>,
+        specular_size: usize,
+        specular_levels: usize,
+    ) -> Self {
+        // The source is mipmapped, such that samples of rough reflections can be taken
+        // from blurrier levels (filtered importance sampling)
+        let mut source = environment.clone();
+        source.generate_mipmaps(MipmapFilter::Box);
+
+        let specular = (0..specular_levels)
+            .map(|level| {
+                let roughness = level as f32 / (specular_levels - 1).max(1) as f32;
+                prefilter_specular(
+                    &source,
+                    specular_size.checked_shr(level as u32).unwrap_or(0).max(1),
+                    roughness,
+                )
+            })
+            .collect();
+
+        Self {
+            intensity: 1.0,
+            irradiance: project_irradiance(environment),
+            specular,
+        }
+    }
+
+    /// Irradiance divided by π arriving at a surface with the given normal
+    ///
+    /// Multiplied with the albedo, this is the radiance diffusely reflected by a
+    /// Lambertian surface. A uniform environment results in its own radiance.
+    pub fn diffuse(&self, normal: Float3) -> Float3 {
+        let basis = spherical_harmonics(normal.normalized());
+        let irradiance = self
+            .irradiance
+            .iter()
+            .zip(basis)
+            .fold(Float3::zeros(), |sum, (&coefficient, y)| {
+                sum + coefficient * y
+            });
+
+        Float3::new(
+            irradiance.x.max(0.0),
+            irradiance.y.max(0.0),
+            irradiance.z.max(0.0),
+        ) * self.intensity
+    }
+
+    /// Prefiltered radiance arriving from the given direction at a surface with the
+    /// given perceptual roughness
+    ///
+    /// The two prefiltered levels closest to the roughness are interpolated.
+    pub fn specular(&self, direction: Float3, roughness: f32) -> Float3 {
+        let Some(last) = self.specular.len().checked_sub(1) else {
+            return Float3::zeros();
+        };
+
+        let level = roughness.clamp(0.0, 1.0) * last as f32;
+        let (index, t) = (level.floor() as usize, level.fract());
+        let mut radiance = self.specular[index].sample(direction);
+        if t > 0.0 {
+            radiance = radiance * (1.0 - t) + self.specular[index + 1].sample(direction) * t;
         }
+
+        radiance * self.intensity
     }
 }
+
+/// Real spherical harmonics of the first three bands in a normalized direction
+fn spherical_harmonics(d: Float3) -> [f32; 9] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+/// Project the radiance of a cube map onto spherical harmonics and convolve it with
+/// the clamped cosine, giving the irradiance divided by π
+///
+/// See Ramamoorthi and Hanrahan, "An Efficient Representation for Irradiance
+/// Environment Maps".
+fn project_irradiance(environment: &CubeMap<Float3>) -> [Float3; 9] {
+    let mut coefficients = [Float3::zeros(); 9];
+    let mut total_solid_angle = 0.0;
+
+    for (face, texture) in environment.faces.iter().enumerate() {
+        let (width, height) = (texture.width as f32, texture.height as f32);
+        for y in 0..texture.height {
+            for x in 0..texture.width {
+                let texture_coord =
+                    Float2::new((x as f32 + 0.5) / width, 1.0 - (y as f32 + 0.5) / height);
+                let direction = CubeMap::<Float3>::face_direction(face, texture_coord);
+
+                // Solid angle covered by the texel on the unit cube
+                let length = direction.dot(direction).sqrt();
+                let solid_angle = 4.0 / (width * height * length * length * length);
+                total_solid_angle += solid_angle;
+
+                let radiance = texture.image[y * texture.width + x];
+                let basis = spherical_harmonics(direction / length);
+                for (coefficient, y) in coefficients.iter_mut().zip(basis) {
+                    *coefficient += radiance * (y * solid_angle);
+                }
+            }
+        }
+    }
+
+    // Correct the approximated solid angles to sum up to the full sphere and convolve
+    // the bands with the clamped cosine (π, 2π/3, π/4) divided by π
+    let normalization = 4.0 * std::f32::consts::PI / total_solid_angle;
+    for (i, coefficient) in coefficients.iter_mut().enumerate() {
+        let band = match i {
+            0 => 1.0,
+            1..=3 => 2.0 / 3.0,
+            _ => 0.25,
+        };
+        *coefficient *= normalization * band;
+    }
+
+    coefficients
+}
+
+/// Convolve the radiance of a mipmapped cube map with the GGX distribution, assuming
+/// that the surface normal and the directions to the eye and of the reflection coincide
+///
+/// See Karis, "Real Shading in Unreal Engine 4".
+fn prefilter_specular(source: &CubeMap<Float3>, size: usize, roughness: f32) -> CubeMap<Float3> {
+    let source_size = source.faces[0].width.max(1) as f32;
+    let texel_solid_angle = 4.0 * std::f32::consts::PI / (6.0 * source_size * source_size);
+    let a = roughness * roughness;
+
+    let faces = std::array::from_fn(|face| {
+        let mut image = Texture::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                let texture_coord = Float2::new(
+                    (x as f32 + 0.5) / size as f32,
+                    1.0 - (y as f32 + 0.5) / size as f32,
+                );
+                let normal = CubeMap::<Float3>::face_direction(face, texture_coord).normalized();
+
+                image.image[y * size + x] = if roughness == 0.0 {
+                    source.sample(normal)
+                } else {
+                    // Orthonormal basis around the normal
+                    let up = if normal.y.abs() < 0.999 {
+                        Float3::unit_y()
+                    } else {
+                        Float3::unit_x()
+                    };
+                    let tangent = up.cross(normal).normalized();
+                    let bitangent = normal.cross(tangent);
+
+                    let (mut sum, mut weight) = (Float3::zeros(), 0.0);
+                    for i in 0..PREFILTER_SAMPLES {
+                        // Importance sample the half-way vector from a Hammersley sequence
+                        let u = i as f32 / PREFILTER_SAMPLES as f32;
+                        let v = i.reverse_bits() as f32 / 4294967296.0;
+                        let phi = 2.0 * std::f32::consts::PI * u;
+                        let cos_theta = ((1.0 - v) / (1.0 + (a * a - 1.0) * v)).sqrt();
+                        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                        let half_way = tangent * (sin_theta * phi.cos())
+                            + bitangent * (sin_theta * phi.sin())
+                            + normal * cos_theta;
+
+                        let dir_to_light = half_way * (2.0 * normal.dot(half_way)) - normal;
+                        let n_dot_l = normal.dot(dir_to_light);
+                        if n_dot_l <= 0.0 {
+                            continue;
+                        }
+
+                        // Take samples covering a larger solid angle from blurrier levels
+                        let pdf = distribution_ggx(cos_theta, roughness) / 4.0;
+                        let sample_solid_angle = 1.0 / (PREFILTER_SAMPLES as f32 * pdf);
+                        let lod = 0.5 * (sample_solid_angle / texel_solid_angle).log2();
+
+                        sum += source.sample_lod(dir_to_light, lod) * n_dot_l;
+                        weight += n_dot_l;
+                    }
+                    sum / weight
+                };
+            }
+        }
+        image
+    });
+
+    CubeMap::new(faces)
+}
+
+/// Shared handles to all lights of a scene
+///
+/// The color of a light is the color a white diffuse surface facing the light reflects,
+/// disregarding attenuation. All shaders follow this convention.
+///
+/// The renderer writes the shadow maps of the lights while shaders read them.
+#[derive(Clone, Default)]
+pub struct Lights {
+    /// Spotlights
+    pub spotlights: Vec<Arc<RwLock<SpotLight>>>,
+    /// Directional lights
+    pub directional_lights: Vec<Arc<RwLock<DirectionalLight>>>,
+    /// Point lights
+    pub point_lights: Vec<Arc<RwLock<PointLight>>>,
+    /// Light of the distant environment, which replaces the constant ambient light
+    pub environment: Option<Arc<EnvironmentLight>>,
+}
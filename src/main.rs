@@ -3,6 +3,7 @@
 use std::f32;
 
 use rastr::math::Float3;
+use rastr::postprocess::fxaa;
 use rastr::render::{RenderTarget, color_buffer_to_byte_array, depth_buffer_to_byte_array};
 use rastr::scene::Scene;
 use raylib::prelude::*;
@@ -34,17 +35,33 @@ fn run(target: &mut RenderTarget, scene: &mut Scene) {
 
     let mut show_depth = false;
 
-    // Spotlight depth buffer
-    let spotlight = scene.spotlights[0].borrow();
-    let spotlight_depth_img = Image::gen_image_color(spotlight.shadow_map.width as i32, spotlight.shadow_map.height as i32, Color::WHITE).from_channel(0);
-    let mut spotlight_depth_texture = rl
-        .load_texture_from_image(&thread, &spotlight_depth_img)
-        .unwrap();
-    let mut spotlight_depth_texture_bytes: Vec<u8> = Vec::new();
-    spotlight_depth_texture_bytes.resize(spotlight.shadow_map.width * spotlight.shadow_map.height, 0); // Grayscale
-    drop(spotlight);
+    // Spotlight depth buffers
+    let mut spotlight_depth_textures = Vec::new();
+    let mut spotlight_depth_texture_bytes = Vec::new();
+    for spotlight in scene.lights.spotlights.iter() {
+        let spotlight = spotlight.read().unwrap();
+        let spotlight_depth_img = Image::gen_image_color(
+            spotlight.shadow_map.width as i32,
+            spotlight.shadow_map.height as i32,
+            Color::WHITE,
+        )
+        .from_channel(0);
+        spotlight_depth_textures.push(
+            rl.load_texture_from_image(&thread, &spotlight_depth_img)
+                .unwrap(),
+        );
+        let size = spotlight.shadow_map.width * spotlight.shadow_map.height;
+        spotlight_depth_texture_bytes.push(vec![0u8; size]); // Grayscale
+    }
+
+    // Index of the spotlight whose depth buffer is shown
+    let mut shown_spotlight_depth: Option<usize> = None;
+
+    // Post-process anti-aliasing
+    let mut fxaa_buffer: Vec<Float3> = Vec::new();
+    fxaa_buffer.resize(target.width * target.height, Float3::zeros());
 
-    let mut show_spotlight_depth = false;
+    let mut use_fxaa = false;
 
     // Render loop
     while !rl.window_should_close() {
@@ -62,18 +79,37 @@ fn run(target: &mut RenderTarget, scene: &mut Scene) {
         }
 
         if rl.is_key_pressed(KeyboardKey::KEY_G) {
-            show_spotlight_depth = !show_spotlight_depth;
+            // Cycle through the spotlights, then hide
+            shown_spotlight_depth = match shown_spotlight_depth {
+                None if !scene.lights.spotlights.is_empty() => Some(0),
+                Some(i) if i + 1 < scene.lights.spotlights.len() => Some(i + 1),
+                _ => None,
+            };
+        }
+
+        if rl.is_key_pressed(KeyboardKey::KEY_X) {
+            use_fxaa = !use_fxaa;
         }
 
         // Update and rasterize scene
         target.clear(Float3::new(0.0, 0.0, 0.0));
-        scene.spotlights[0].borrow_mut().shadow_map.image.fill(1.0);
         target.render(scene);
 
         if !show_depth {
             // Write rasterizer output to texture and display on window
+            let color_buffer = if use_fxaa {
+                fxaa(
+                    &target.color_buffer,
+                    target.width,
+                    target.height,
+                    &mut fxaa_buffer,
+                );
+                &fxaa_buffer
+            } else {
+                &target.color_buffer
+            };
             color_buffer_to_byte_array(
-                &target.color_buffer,
+                color_buffer,
                 target.width,
                 target.height,
                 &mut texture_bytes,
@@ -93,8 +129,8 @@ fn run(target: &mut RenderTarget, scene: &mut Scene) {
             depth_texture.update_texture(&depth_texture_bytes).unwrap();
         }
 
-        if show_spotlight_depth {
-            let spotlight = scene.spotlights[0].borrow();
+        if let Some(i) = shown_spotlight_depth {
+            let spotlight = scene.lights.spotlights[i].read().unwrap();
             depth_buffer_to_byte_array(
                 &spotlight.shadow_map.image,
                 spotlight.shadow_map.width,
@@ -102,9 +138,11 @@ fn run(target: &mut RenderTarget, scene: &mut Scene) {
                 spotlight.camera.near,
                 spotlight.camera.far,
                 true,
-                &mut spotlight_depth_texture_bytes,
+                &mut spotlight_depth_texture_bytes[i],
             );
-            spotlight_depth_texture.update_texture(&spotlight_depth_texture_bytes).unwrap();
+            spotlight_depth_textures[i]
+                .update_texture(&spotlight_depth_texture_bytes[i])
+                .unwrap();
         }
 
         let mut d = rl.begin_drawing(&thread);
@@ -114,9 +152,10 @@ fn run(target: &mut RenderTarget, scene: &mut Scene) {
             d.draw_texture(&depth_texture, 0, 0, Color::WHITE);
         }
 
-        if show_spotlight_depth {
+        if let Some(i) = shown_spotlight_depth {
+            let spotlight_depth_texture = &spotlight_depth_textures[i];
             d.draw_texture(
-                &spotlight_depth_texture,
+                spotlight_depth_texture,
                 target.width as i32 - spotlight_depth_texture.width - 10,
                 10,
                 Color::WHITE,
@@ -140,14 +179,22 @@ fn run(target: &mut RenderTarget, scene: &mut Scene) {
             12,
             Color::WHITE,
         );
+        d.draw_text(
+            &format!("FXAA: {}", if use_fxaa { "on" } else { "off" }),
+            0,
+            24,
+            12,
+            Color::WHITE,
+        );
     }
 }
 
 fn main() {
     const WIDTH: usize = 1024;
     const HEIGHT: usize = 768;
+    const SAMPLES: usize = 4;
 
-    let mut target = RenderTarget::new(WIDTH, HEIGHT);
+    let mut target = RenderTarget::new(WIDTH, HEIGHT, SAMPLES);
     let mut scene = Scene::new(WIDTH as f32 / HEIGHT as f32);
 
     run(&mut target, &mut scene)
@@ -0,0 +1,147 @@
+use crate::math::{Float3, Float4};
+use crate::texture::{Sampler, Texture, TextureCoordinates};
+
+/// Surface parameters and textures of a model
+///
+/// A material only describes the surface. How it reacts to light is decided by the
+/// [pixel shader](crate::shader::PixelShader) of the model, which receives the lights
+/// and the camera every frame. Materials can therefore be shared between models with
+/// different lighting models. Each shader reads the parameters it needs and ignores
+/// the rest.
+pub struct Material {
+    /// Base color of the surface (diffuse color, albedo of dielectrics, specular color
+    /// of metals)
+    pub base_color: Float3,
+    /// Texture multiplied with the base color, whose alpha channel is multiplied with
+    /// the opacity
+    pub base_color_texture: Option<Texture<Float4>>,
+    /// Specular color of the surface
+    pub specular_color: Float3,
+    /// Texture multiplied with the specular color
+    pub specular_texture: Option<Texture<Float3>>,
+    /// Exponent of the specular highlight, higher values give smaller highlights
+    pub shininess: f32,
+    /// Metalness in [0, 1]
+    pub metallic: f32,
+    /// Perceptual roughness in [0, 1]
+    pub roughness: f32,
+    /// Texture whose green and blue channel are multiplied with roughness and metalness
+    pub metallic_roughness_texture: Option<Texture<Float3>>,
+    /// Ambient occlusion in [0, 1] where 0 is fully occluded
+    pub ambient_occlusion: f32,
+    /// Texture whose red channel is multiplied with the ambient occlusion
+    pub occlusion_texture: Option<Texture<Float3>>,
+    /// Tangent-space normal map perturbing the surface normal
+    pub normal_map: Option<Texture<Float3>>,
+    /// Sampler used for all textures of the material
+    pub sampler: Sampler<Float3>,
+    /// Opacity in [0, 1]
+    pub alpha: f32,
+    /// Texture whose red channel is multiplied with the opacity
+    pub alpha_texture: Option<Texture<Float3>>,
+    /// Fragments with an opacity below the cutoff are discarded (alpha testing)
+    pub alpha_cutoff: Option<f32>,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color: Float3::ones(),
+            base_color_texture: None,
+            specular_color: Float3::ones(),
+            specular_texture: None,
+            shininess: 32.0,
+            metallic: 0.0,
+            roughness: 0.5,
+            metallic_roughness_texture: None,
+            ambient_occlusion: 1.0,
+            occlusion_texture: None,
+            normal_map: None,
+            sampler: Sampler::default(),
+            alpha: 1.0,
+            alpha_texture: None,
+            alpha_cutoff: None,
+        }
+    }
+}
+
+impl Material {
+    /// Create a new material with the given base color and default parameters
+    pub fn new(base_color: Float3) -> Self {
+        Self {
+            base_color,
+            ..Default::default()
+        }
+    }
+
+    /// Base color at the given texture coordinates
+    pub fn base_color(&self, uv: TextureCoordinates) -> Float3 {
+        match &self.base_color_texture {
+            Some(texture) => self.base_color * self.sample_with_alpha(texture, uv).xyz(),
+            None => self.base_color,
+        }
+    }
+
+    /// Specular color at the given texture coordinates
+    pub fn specular_color(&self, uv: TextureCoordinates) -> Float3 {
+        match &self.specular_texture {
+            Some(texture) => self.specular_color * self.sample(texture, uv),
+            None => self.specular_color,
+        }
+    }
+
+    /// Metalness and roughness at the given texture coordinates
+    pub fn metallic_roughness(&self, uv: TextureCoordinates) -> (f32, f32) {
+        match &self.metallic_roughness_texture {
+            Some(texture) => {
+                let texel = self.sample(texture, uv);
+                (self.metallic * texel.z, self.roughness * texel.y)
+            }
+            None => (self.metallic, self.roughness),
+        }
+    }
+
+    /// Ambient occlusion at the given texture coordinates
+    pub fn ambient_occlusion(&self, uv: TextureCoordinates) -> f32 {
+        match &self.occlusion_texture {
+            Some(texture) => self.ambient_occlusion * self.sample(texture, uv).x,
+            None => self.ambient_occlusion,
+        }
+    }
+
+    /// Tangent-space normal at the given texture coordinates, if there is a normal map
+    ///
+    /// The components are mapped from [0, 1] in the normal map back to [-1, 1].
+    pub fn tangent_space_normal(&self, uv: TextureCoordinates) -> Option<Float3> {
+        self.normal_map
+            .as_ref()
+            .map(|texture| self.sample(texture, uv) * 2.0 - Float3::ones())
+    }
+
+    /// Opacity at the given texture coordinates
+    ///
+    /// The opacity is multiplied with the alpha channel of the base color texture and
+    /// the red channel of the alpha texture, if there are any.
+    pub fn alpha(&self, uv: TextureCoordinates) -> f32 {
+        let base_color_alpha = match &self.base_color_texture {
+            Some(texture) => self.sample_with_alpha(texture, uv).w,
+            None => 1.0,
+        };
+        let mask = match &self.alpha_texture {
+            Some(texture) => self.sample(texture, uv).x,
+            None => 1.0,
+        };
+
+        self.alpha * base_color_alpha * mask
+    }
+
+    /// Sample a texture of the material with the material's sampler
+    fn sample(&self, texture: &Texture<Float3>, uv: TextureCoordinates) -> Float3 {
+        texture.sample_grad(&self.sampler, uv.uv, uv.duv_dx, uv.duv_dy)
+    }
+
+    /// Sample a texture with alpha channel of the material with the material's sampler
+    fn sample_with_alpha(&self, texture: &Texture<Float4>, uv: TextureCoordinates) -> Float4 {
+        texture.sample_grad(&self.sampler.with_alpha(), uv.uv, uv.duv_dx, uv.duv_dy)
+    }
+}
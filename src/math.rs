@@ -47,6 +47,12 @@ impl Float2 {
     }
 }
 
+impl Default for Float2 {
+    fn default() -> Self {
+        Self::zeros()
+    }
+}
+
 impl Add for Float2 {
     type Output = Self;
 
@@ -1718,6 +1724,12 @@ impl Float4 {
     }
 }
 
+impl Default for Float4 {
+    fn default() -> Self {
+        Self::zeros()
+    }
+}
+
 impl Add for Float4 {
     type Output = Self;
 
@@ -2545,6 +2557,11 @@ impl Float4x4 {
         )
     }
 
+    /// Orthographic projection of the box between the near and far plane
+    ///
+    /// Like the perspective projection, the resulting homogeneous coordinate w is
+    /// negative (w = -1) for points in front of the camera, so that both projections
+    /// share the same clipping and perspective divide.
     pub fn orthographic_projection(
         near: f32,
         far: f32,
@@ -2555,24 +2572,19 @@ impl Float4x4 {
     ) -> Self {
         Self::new(
             Float4::new(
-                2.0 * near / (right - left),
+                -2.0 / (right - left),
                 0.0,
-                -(right + left) / (right - left),
-                0.0,
-            ),
-            Float4::new(
-                0.0,
-                2.0 * near / (top - bottom),
-                -(top + bottom) / (top - bottom),
                 0.0,
+                (right + left) / (right - left),
             ),
             Float4::new(
                 0.0,
+                -2.0 / (top - bottom),
                 0.0,
-                (far + near) / (far - near),
-                -2.0 * far * near / (far - near),
+                (top + bottom) / (top - bottom),
             ),
-            Float4::new(0.0, 0.0, 1.0, 0.0),
+            Float4::new(0.0, 0.0, -2.0 / (far - near), (far + near) / (far - near)),
+            Float4::new(0.0, 0.0, 0.0, -1.0),
         )
     }
 
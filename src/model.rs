@@ -1,7 +1,41 @@
-use crate::math::{Float2, Float3};
-use crate::shader::PixelShader;
+use crate::material::Material;
+use crate::math::{Float2, Float3, Float4};
+use crate::shader::ModelShader;
 use crate::transform::Transform;
+use std::collections::HashMap;
 use std::fs::read_to_string;
+use std::sync::Arc;
+
+/// Selection of triangle faces which are discarded before rasterization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CullMode {
+    /// Discard back-facing triangles
+    Back,
+    /// Discard front-facing triangles
+    Front,
+    /// Render both faces of triangles (double-sided)
+    None,
+}
+
+impl CullMode {
+    /// Whether a triangle facing the given way is discarded
+    pub fn culls(&self, front_facing: bool) -> bool {
+        match self {
+            CullMode::Back => !front_facing,
+            CullMode::Front => front_facing,
+            CullMode::None => false,
+        }
+    }
+}
+
+/// Winding order of the vertices of front-facing triangles as seen on screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontFace {
+    /// Vertices of front-facing triangles appear in counter-clockwise order
+    CounterClockwise,
+    /// Vertices of front-facing triangles appear in clockwise order
+    Clockwise,
+}
 
 /// A model described by triangles with normals and texture coordinates
 pub struct Model {
@@ -20,14 +54,30 @@ pub struct Model {
     /// Indices of normals in groups of 3 for each triangle.
     /// Indices are with referece to [normals](Model::normals)
     pub normal_indices: Vec<usize>,
+    /// Tangents at each vertex, pointing in the direction of increasing u
+    ///
+    /// The w-component holds the sign of the bitangent, which is
+    /// `w * normal.cross(tangent)` and points in the direction of increasing v.
+    pub tangents: Vec<Float4>,
+    /// Indices of tangents in groups of 3 for each triangle.
+    /// Indices are with referece to [tangents](Model::tangents)
+    pub tangent_indices: Vec<usize>,
     /// Transformation of the model
     pub transform: Transform,
-    /// Pixel shader applied to model
-    pub shader: Box<dyn PixelShader>,
+    /// Shaders applied to model
+    pub shader: Box<dyn ModelShader>,
+    /// Material of the model's surface, may be shared with other models
+    pub material: Arc<Material>,
+    /// Faces of the model's triangles which are culled (back faces by default)
+    pub cull_mode: CullMode,
+    /// Winding order of front-facing triangles (counter-clockwise by default)
+    pub front_face: FrontFace,
 }
 
 impl Model {
     /// Create a new model
+    ///
+    /// Tangents are generated from the vertices, texture coordinates and normals.
     pub fn new(
         vertices: Vec<Float3>,
         vertex_indices: Vec<usize>,
@@ -36,8 +86,18 @@ impl Model {
         normals: Vec<Float3>,
         normal_indices: Vec<usize>,
         transform: Transform,
-        shader: Box<dyn PixelShader>,
+        shader: Box<dyn ModelShader>,
+        material: Arc<Material>,
     ) -> Self {
+        let (tangents, tangent_indices) = generate_tangents(
+            &vertices,
+            &vertex_indices,
+            &texture_coords,
+            &texture_coord_indices,
+            &normals,
+            &normal_indices,
+        );
+
         Self {
             vertices,
             vertex_indices,
@@ -45,10 +105,97 @@ impl Model {
             texture_coord_indices,
             normals,
             normal_indices,
+            tangents,
+            tangent_indices,
             transform,
             shader,
+            material,
+            cull_mode: CullMode::Back,
+            front_face: FrontFace::CounterClockwise,
+        }
+    }
+}
+
+/// Generate tangents for normal mapping
+///
+/// The tangent and bitangent of each triangle are computed from the derivatives of the
+/// vertex positions with respect to the texture coordinates. They are accumulated for
+/// every distinct combination of vertex, texture coordinate, and normal, so that texture
+/// seams and hard edges get separate tangents. Finally, the tangents are orthogonalized
+/// against the normals (Gram-Schmidt) and the handedness of the bitangent is stored in
+/// the w-component.
+///
+/// Returns the tangents and the indices of tangents in groups of 3 for each triangle.
+pub fn generate_tangents(
+    vertices: &[Float3],
+    vertex_indices: &[usize],
+    texture_coords: &[Float2],
+    texture_coord_indices: &[usize],
+    normals: &[Float3],
+    normal_indices: &[usize],
+) -> (Vec<Float4>, Vec<usize>) {
+    let mut corners = HashMap::new();
+    let mut accumulated: Vec<(Float3, Float3, usize)> = Vec::new();
+    let mut tangent_indices = Vec::with_capacity(vertex_indices.len());
+
+    for ((vs, uvs), ns) in vertex_indices
+        .chunks_exact(3)
+        .zip(texture_coord_indices.chunks_exact(3))
+        .zip(normal_indices.chunks_exact(3))
+    {
+        let edge1 = vertices[vs[1]] - vertices[vs[0]];
+        let edge2 = vertices[vs[2]] - vertices[vs[0]];
+        let duv1 = texture_coords[uvs[1]] - texture_coords[uvs[0]];
+        let duv2 = texture_coords[uvs[2]] - texture_coords[uvs[0]];
+
+        let det = duv1.x * duv2.y - duv2.x * duv1.y;
+        let (tangent, bitangent) = if det.abs() > f32::EPSILON {
+            (
+                (edge1 * duv2.y - edge2 * duv1.y) / det,
+                (edge2 * duv1.x - edge1 * duv2.x) / det,
+            )
+        } else {
+            // Degenerate texture coordinates do not contribute
+            (Float3::zeros(), Float3::zeros())
+        };
+
+        for i in 0..3 {
+            let idx = *corners.entry((vs[i], uvs[i], ns[i])).or_insert_with(|| {
+                accumulated.push((Float3::zeros(), Float3::zeros(), ns[i]));
+                accumulated.len() - 1
+            });
+            accumulated[idx].0 += tangent;
+            accumulated[idx].1 += bitangent;
+            tangent_indices.push(idx);
         }
     }
+
+    let tangents = accumulated
+        .into_iter()
+        .map(|(tangent, bitangent, nidx)| {
+            let normal = normals[nidx].normalized();
+            let mut t = tangent - normal * normal.dot(tangent);
+            if t.dot(t) <= f32::EPSILON {
+                // Pick any direction perpendicular to the normal
+                let axis = if normal.x.abs() < 0.9 {
+                    Float3::new(1.0, 0.0, 0.0)
+                } else {
+                    Float3::new(0.0, 1.0, 0.0)
+                };
+                t = axis - normal * normal.dot(axis);
+            }
+            let t = t.normalized();
+            let handedness = if normal.cross(t).dot(bitangent) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+
+            Float4::new(t.x, t.y, t.z, handedness)
+        })
+        .collect();
+
+    (tangents, tangent_indices)
 }
 
 /// Read an OBJ file
@@ -0,0 +1,225 @@
+use crate::math::Float3;
+
+/// Minimal local contrast required for a pixel to be considered part of an edge
+const FXAA_EDGE_THRESHOLD_MIN: f32 = 0.0312;
+/// Minimal local contrast relative to the brightest neighbor for a pixel to be
+/// considered part of an edge
+const FXAA_EDGE_THRESHOLD: f32 = 0.125;
+/// Amount of sub-pixel aliasing removal (0 turns it off, 1 is the softest)
+const FXAA_SUBPIXEL_QUALITY: f32 = 0.75;
+/// Step sizes in pixels used while searching for the end points of an edge
+const FXAA_SEARCH_STEPS: [f32; 12] = [1.0, 1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 2.0, 4.0, 8.0];
+
+/// Perceived brightness of a color
+fn luma(color: Float3) -> f32 {
+    color.dot(Float3::new(0.299, 0.587, 0.114))
+}
+
+/// Bilinear lookup in a buffer at continuous pixel coordinates
+///
+/// Pixel centers lie at half-integer coordinates and coordinates outside of the buffer
+/// are clamped to its border.
+pub(crate) fn sample_bilinear<T>(buffer: &[T], width: usize, height: usize, x: f32, y: f32) -> T
+where
+    T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+{
+    let fx = (x - 0.5).clamp(0.0, (width - 1) as f32);
+    let fy = (y - 0.5).clamp(0.0, (height - 1) as f32);
+    let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+
+    let top = buffer[y0 * width + x0] * (1.0 - tx) + buffer[y0 * width + x1] * tx;
+    let bottom = buffer[y1 * width + x0] * (1.0 - tx) + buffer[y1 * width + x1] * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// Apply fast approximate anti-aliasing (FXAA) to a color buffer in RGB format
+///
+/// Edges are detected by local contrast in luma. For every edge pixel, the end points
+/// of the edge are searched in both directions and the pixel is blended with its
+/// neighbor across the edge depending on its position along the edge. Additionally,
+/// pixels which differ strongly from their surrounding (sub-pixel aliasing) are
+/// smoothed. The result is written to `output`.
+///
+/// This follows the FXAA 3.11 quality preset by Timothy Lottes.
+pub fn fxaa(color_buffer: &[Float3], width: usize, height: usize, output: &mut [Float3]) {
+    let lumas = color_buffer.iter().map(|&c| luma(c)).collect::<Vec<_>>();
+    let luma_at = |x: isize, y: isize| {
+        let x = x.clamp(0, width as isize - 1) as usize;
+        let y = y.clamp(0, height as isize - 1) as usize;
+        lumas[y * width + x]
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let (xi, yi) = (x as isize, y as isize);
+            let luma_center = lumas[y * width + x];
+            let luma_north = luma_at(xi, yi - 1);
+            let luma_south = luma_at(xi, yi + 1);
+            let luma_east = luma_at(xi + 1, yi);
+            let luma_west = luma_at(xi - 1, yi);
+
+            // Skip pixels without sufficient local contrast
+            let luma_max = luma_center
+                .max(luma_north)
+                .max(luma_south)
+                .max(luma_east)
+                .max(luma_west);
+            let luma_min = luma_center
+                .min(luma_north)
+                .min(luma_south)
+                .min(luma_east)
+                .min(luma_west);
+            let luma_range = luma_max - luma_min;
+
+            if luma_range < FXAA_EDGE_THRESHOLD_MIN.max(luma_max * FXAA_EDGE_THRESHOLD) {
+                output[y * width + x] = color_buffer[y * width + x];
+                continue;
+            }
+
+            let luma_north_west = luma_at(xi - 1, yi - 1);
+            let luma_north_east = luma_at(xi + 1, yi - 1);
+            let luma_south_west = luma_at(xi - 1, yi + 1);
+            let luma_south_east = luma_at(xi + 1, yi + 1);
+
+            // Estimate edge orientation
+            let edge_horizontal = (luma_north + luma_south - 2.0 * luma_center).abs() * 2.0
+                + (luma_north_east + luma_south_east - 2.0 * luma_east).abs()
+                + (luma_north_west + luma_south_west - 2.0 * luma_west).abs();
+            let edge_vertical = (luma_west + luma_east - 2.0 * luma_center).abs() * 2.0
+                + (luma_north_west + luma_north_east - 2.0 * luma_north).abs()
+                + (luma_south_west + luma_south_east - 2.0 * luma_south).abs();
+            let is_horizontal = edge_horizontal >= edge_vertical;
+
+            // Choose the side of the edge with the steeper gradient
+            let (luma_negative, luma_positive) = if is_horizontal {
+                (luma_north, luma_south)
+            } else {
+                (luma_west, luma_east)
+            };
+            let gradient_negative = luma_negative - luma_center;
+            let gradient_positive = luma_positive - luma_center;
+            let negative_steepest = gradient_negative.abs() >= gradient_positive.abs();
+            let gradient_scaled = 0.25 * gradient_negative.abs().max(gradient_positive.abs());
+
+            let (step_length, luma_local_average) = if negative_steepest {
+                (-1.0, 0.5 * (luma_negative + luma_center))
+            } else {
+                (1.0, 0.5 * (luma_positive + luma_center))
+            };
+
+            // Start in between the pixel and its neighbor across the edge
+            let (center_x, center_y) = (x as f32 + 0.5, y as f32 + 0.5);
+            let (start_x, start_y, offset_x, offset_y) = if is_horizontal {
+                (center_x, center_y + 0.5 * step_length, 1.0, 0.0)
+            } else {
+                (center_x + 0.5 * step_length, center_y, 0.0, 1.0)
+            };
+
+            // Search for the end points of the edge in both directions
+            let search = |direction: f32| {
+                let mut distance = 0.0;
+                let mut delta = 0.0;
+                for step in FXAA_SEARCH_STEPS {
+                    distance += step;
+                    delta = sample_bilinear(
+                        &lumas,
+                        width,
+                        height,
+                        start_x + direction * distance * offset_x,
+                        start_y + direction * distance * offset_y,
+                    ) - luma_local_average;
+
+                    if delta.abs() >= gradient_scaled {
+                        break;
+                    }
+                }
+                (distance, delta)
+            };
+            let (distance_negative, delta_negative) = search(-1.0);
+            let (distance_positive, delta_positive) = search(1.0);
+
+            // Offset towards the edge depending on the position along the edge
+            let (distance, delta) = if distance_negative < distance_positive {
+                (distance_negative, delta_negative)
+            } else {
+                (distance_positive, delta_positive)
+            };
+            let edge_length = distance_negative + distance_positive;
+            let center_smaller = luma_center < luma_local_average;
+            let edge_offset = if (delta < 0.0) != center_smaller {
+                0.5 - distance / edge_length
+            } else {
+                0.0
+            };
+
+            // Sub-pixel anti-aliasing
+            let luma_average = (2.0 * (luma_north + luma_south + luma_east + luma_west)
+                + luma_north_west
+                + luma_north_east
+                + luma_south_west
+                + luma_south_east)
+                / 12.0;
+            let subpixel = ((luma_average - luma_center).abs() / luma_range).clamp(0.0, 1.0);
+            let subpixel = (-2.0 * subpixel + 3.0) * subpixel * subpixel;
+            let subpixel_offset = subpixel * subpixel * FXAA_SUBPIXEL_QUALITY;
+
+            let offset = edge_offset.max(subpixel_offset) * step_length;
+            let (sample_x, sample_y) = if is_horizontal {
+                (center_x, center_y + offset)
+            } else {
+                (center_x + offset, center_y)
+            };
+
+            output[y * width + x] =
+                sample_bilinear(color_buffer, width, height, sample_x, sample_y);
+        }
+    }
+}
+
+/// Blur a buffer with a separable Gaussian filter
+///
+/// The filter extends `radius` pixels in each direction with a standard deviation of half
+/// the radius. Pixels outside of the buffer are clamped to its border.
+pub fn gaussian_blur<T>(buffer: &mut [T], width: usize, height: usize, radius: usize)
+where
+    T: Copy + std::ops::Add<Output = T> + std::ops::Mul<f32, Output = T>,
+{
+    if radius == 0 {
+        return;
+    }
+
+    let sigma = radius as f32 / 2.0;
+    let weights = (0..=2 * radius)
+        .map(|i| {
+            let d = i as f32 - radius as f32;
+            (-d * d / (2.0 * sigma * sigma)).exp()
+        })
+        .collect::<Vec<_>>();
+    let total = weights.iter().sum::<f32>();
+    let weights = weights.iter().map(|w| w / total).collect::<Vec<_>>();
+
+    let convolve = |input: &[T], output: &mut [T], step_x: isize, step_y: isize| {
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = input[y * width + x] * weights[radius];
+                for (i, &weight) in weights.iter().enumerate() {
+                    if i == radius {
+                        continue;
+                    }
+                    let d = i as isize - radius as isize;
+                    let sx = (x as isize + d * step_x).clamp(0, width as isize - 1) as usize;
+                    let sy = (y as isize + d * step_y).clamp(0, height as isize - 1) as usize;
+                    sum = sum + input[sy * width + sx] * weight;
+                }
+                output[y * width + x] = sum;
+            }
+        }
+    };
+
+    // Horizontal pass followed by vertical pass
+    let mut horizontal = buffer.to_vec();
+    convolve(buffer, &mut horizontal, 1, 0);
+    convolve(&horizontal, buffer, 0, 1);
+}
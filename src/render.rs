@@ -1,13 +1,21 @@
 use core::f32;
 use std::fmt::Debug;
 use std::ops::{Add, Mul};
+use std::sync::Mutex;
+use std::thread;
 
-use crate::math::{
-    Float2, Float3, Float4, point_in_triangle_back_face, point_in_triangle_front_face,
-    signed_triangle_area,
-};
+use crate::camera::Camera;
+use crate::light::{ShadowBias, ShadowFilter};
+use crate::material::Material;
+use crate::math::{Float2, Float3, Float4};
+use crate::model::{CullMode, FrontFace, Model};
+use crate::postprocess::gaussian_blur;
 use crate::scene::Scene;
-use crate::shader::{RenderPassShader, RenderPassShaderInput, ShadowPassShader, ShadowPassShaderInput, VertexShader};
+use crate::shader::{
+    PixelShader, PixelShaderInput, PixelShaderOutput, RenderPassShaderInput,
+    RenderPassShaderOutput, ShadingContext, culling_bitmask, shadow_map_moments,
+};
+use crate::texture::{CubeMap, Texture};
 
 /// Trait used for types that support linear interpolation
 pub trait LinearInterpolation {
@@ -41,27 +49,42 @@ impl Mul<f32> for EmptyAttributes {
     }
 }
 
+impl LinearInterpolation for Float2 {
+    fn lerp(&self, other: &Self, proportion: f32) -> Self {
+        Float2::lerp(self, *other, proportion)
+    }
+}
+
 /// A set of vertex attributes used for shading
 #[derive(Debug, Clone, Copy)]
 pub struct VertexAttributes {
     /// Vertex position in world space
     pub vertex: Float3,
-    /// Homogeneous vertex position in light's clip space
-    pub light_vertex: Float4,
     /// Texture coordinates
     pub uv: Float2,
     /// Normal
     pub normal: Float3,
+    /// Tangent with the bitangent sign in the w-component
+    pub tangent: Float4,
+    /// Vector from the vertex to the camera in world space
+    pub to_eye: Float3,
 }
 
 impl VertexAttributes {
     /// Create new vertex attributes
-    pub fn new(vertex: Float3, light_vertex: Float4, uv: Float2, normal: Float3) -> Self {
+    pub fn new(
+        vertex: Float3,
+        uv: Float2,
+        normal: Float3,
+        tangent: Float4,
+        to_eye: Float3,
+    ) -> Self {
         Self {
             vertex,
-            light_vertex,
             uv,
             normal,
+            tangent,
+            to_eye,
         }
     }
 }
@@ -69,11 +92,12 @@ impl VertexAttributes {
 impl LinearInterpolation for VertexAttributes {
     fn lerp(&self, other: &Self, proportion: f32) -> Self {
         let vertex = self.vertex.lerp(other.vertex, proportion);
-        let light_vertex = self.light_vertex.lerp(other.light_vertex, proportion);
         let uv = self.uv.lerp(other.uv, proportion);
         let normal = self.normal.lerp(other.normal, proportion);
+        let tangent = self.tangent.lerp(other.tangent, proportion);
+        let to_eye = self.to_eye.lerp(other.to_eye, proportion);
 
-        Self::new(vertex, light_vertex, uv, normal)
+        Self::new(vertex, uv, normal, tangent, to_eye)
     }
 }
 
@@ -83,9 +107,10 @@ impl Add for VertexAttributes {
     fn add(self, rhs: Self) -> Self::Output {
         Self {
             vertex: self.vertex + rhs.vertex,
-            light_vertex: self.light_vertex + rhs.light_vertex,
             uv: self.uv + rhs.uv,
             normal: self.normal + rhs.normal,
+            tangent: self.tangent + rhs.tangent,
+            to_eye: self.to_eye + rhs.to_eye,
         }
     }
 }
@@ -96,13 +121,43 @@ impl Mul<f32> for VertexAttributes {
     fn mul(self, rhs: f32) -> Self::Output {
         Self {
             vertex: self.vertex * rhs,
-            light_vertex: self.light_vertex * rhs,
             uv: self.uv * rhs,
             normal: self.normal * rhs,
+            tangent: self.tangent * rhs,
+            to_eye: self.to_eye * rhs,
         }
     }
 }
 
+impl Varyings for VertexAttributes {
+    fn uv(&self) -> Option<Float2> {
+        Some(self.uv)
+    }
+}
+
+/// Vertex attributes output by a render pass vertex shader
+///
+/// The attributes are clipped and interpolated with perspective correction across
+/// triangles before they are handed to the pixel shader.
+pub trait Varyings:
+    Debug
+    + Clone
+    + Copy
+    + LinearInterpolation
+    + Add<Output = Self>
+    + Mul<f32, Output = Self>
+    + Send
+    + Sync
+{
+    /// Texture coordinates at which the material is sampled
+    ///
+    /// Varyings without texture coordinates return `None`, in which case the material's
+    /// textures are not sampled by the default [shade](crate::shader::PixelShader::shade).
+    fn uv(&self) -> Option<Float2> {
+        None
+    }
+}
+
 #[derive(Debug)]
 struct Triangle<T>
 where
@@ -122,13 +177,708 @@ where
             vertex_attributes,
         }
     }
+}
+
+/// Edge length in pixels of the square tiles the screen is split into during rasterization
+const TILE_SIZE: usize = 32;
+
+/// Number of fractional bits of the fixed-point screen coordinates used for rasterization
+const SUBPIXEL_BITS: u32 = 8;
+
+/// One pixel in fixed-point screen coordinates
+const SUBPIXEL_ONE: i64 = 1 << SUBPIXEL_BITS;
+
+/// Maximal number of samples per pixel supported for multisampling
+pub const MAX_SAMPLES: usize = 8;
+
+/// Standard sample positions for 1, 2, 4, and 8 samples per pixel
+///
+/// Positions are given in 1/16 pixels relative to the pixel center and follow the
+/// standard multisample patterns of Direct3D and Vulkan.
+fn sample_pattern(samples: usize) -> &'static [(i64, i64)] {
+    match samples {
+        1 => &[(0, 0)],
+        2 => &[(4, 4), (-4, -4)],
+        4 => &[(-2, -6), (6, -2), (-6, 2), (2, 6)],
+        8 => &[
+            (1, -3),
+            (-1, 3),
+            (5, 1),
+            (-3, -5),
+            (-5, 5),
+            (-7, -1),
+            (3, 7),
+            (7, -7),
+        ],
+        _ => panic!("Unsupported number of samples per pixel: {samples}"),
+    }
+}
+
+/// Snap a position in screen space to fixed-point coordinates
+fn snap_to_subpixel(p: Float2) -> (i64, i64) {
+    (
+        (p.x * SUBPIXEL_ONE as f32).round() as i64,
+        (p.y * SUBPIXEL_ONE as f32).round() as i64,
+    )
+}
+
+/// Edge function of a directed triangle edge in fixed-point screen coordinates
+///
+/// The edge function is twice the signed area of the triangle spanned by the edge
+/// and a point. It is positive for points to the right of the edge (in screen
+/// space with y pointing downwards), i.e. inside of front-facing triangles.
+#[derive(Debug, Clone, Copy)]
+struct EdgeFunction {
+    /// Start of the edge
+    origin: (i64, i64),
+    /// Change of the edge function per pixel step in x-direction
+    step_x: i64,
+    /// Change of the edge function per pixel step in y-direction
+    step_y: i64,
+    /// Offset applied before the inside test implementing the top-left fill rule
+    bias: i64,
+}
+
+impl EdgeFunction {
+    /// Create the edge function of the directed edge from `v0` to `v1`
+    fn new(v0: (i64, i64), v1: (i64, i64)) -> Self {
+        let (dx, dy) = (v1.0 - v0.0, v1.1 - v0.1);
+
+        // Top-left fill rule: Pixel centers exactly on an edge are only covered if the
+        // edge is a left edge (the interior lies in positive x-direction) or a top edge
+        // (horizontal with the interior below). Shared edges are thus rasterized exactly
+        // once.
+        let top_left = dy > 0 || (dy == 0 && dx < 0);
+
+        Self {
+            origin: v0,
+            step_x: dy * SUBPIXEL_ONE,
+            step_y: -dx * SUBPIXEL_ONE,
+            bias: if top_left { 0 } else { -1 },
+        }
+    }
+
+    /// Evaluate the edge function at a point in fixed-point coordinates
+    fn evaluate(&self, p: (i64, i64)) -> i64 {
+        self.offset((p.0 - self.origin.0, p.1 - self.origin.1))
+    }
+
+    /// Change of the edge function when moving by an offset in fixed-point coordinates
+    fn offset(&self, d: (i64, i64)) -> i64 {
+        (d.0 * self.step_x + d.1 * self.step_y) / SUBPIXEL_ONE
+    }
+
+    /// Test whether a value of the edge function lies inside of the triangle
+    fn inside(&self, value: i64) -> bool {
+        value + self.bias >= 0
+    }
+}
+
+/// Coverage of a single pixel by a triangle
+struct Fragment {
+    /// Horizontal pixel coordinate
+    x: usize,
+    /// Vertical pixel coordinate
+    y: usize,
+    /// Bitmask of the samples covered by the triangle
+    coverage: u32,
+    /// Barycentric weights at each covered sample
+    sample_weights: [Float3; MAX_SAMPLES],
+    /// Barycentric weights used for shading
+    ///
+    /// These are taken at the pixel center if it is covered and otherwise at the
+    /// first covered sample (centroid sampling), such that attributes are never
+    /// extrapolated beyond the triangle.
+    weights: Float3,
+}
+
+impl Fragment {
+    /// Indices of the samples covered by the triangle
+    fn covered_samples(&self) -> impl Iterator<Item = usize> + use<> {
+        let coverage = self.coverage;
+        (0..MAX_SAMPLES).filter(move |s| coverage & (1 << s) != 0)
+    }
+}
+
+/// A triangle in screen space which is ready for rasterization
+#[derive(Debug)]
+struct RasterTriangle<T>
+where
+    T: Debug + Clone + Copy + LinearInterpolation + Add<Output = T> + Mul<f32>,
+{
+    /// Edge functions of the edges opposite to each vertex
+    edges: [EdgeFunction; 3],
+    /// Inverse of twice the triangle area in fixed-point coordinates
+    inverse_area: f32,
+    /// Depths of the vertices in [0, 1]
+    depths: Float3,
+    /// Inverse of the view depths (clip space w) of the vertices
+    inverse_view_depths: Float3,
+    /// Vertex attributes which are interpolated across the triangle
+    vertex_attributes: [T; 3],
+    /// Bounding box in pixels given as `(start_x, start_y, end_x, end_y)`, where
+    /// the end is exclusive
+    bbox: (usize, usize, usize, usize),
+    /// Index of the triangle within its model before clipping
+    primitive: usize,
+    /// Whether the front face of the triangle is visible
+    front_facing: bool,
+}
+
+impl<T> RasterTriangle<T>
+where
+    T: Debug + Clone + Copy + LinearInterpolation + Add<Output = T> + Mul<f32, Output = T>,
+{
+    /// Project a triangle in clip space onto a screen of the given size
+    ///
+    /// Vertices are snapped to fixed-point coordinates with `SUBPIXEL_BITS` bits of
+    /// sub-pixel precision. Whether the triangle is front-facing is determined by the
+    /// winding order of its vertices on screen. Returns `None` if the triangle is culled
+    /// or degenerate.
+    fn new(
+        mut triangle: Triangle<T>,
+        width: usize,
+        height: usize,
+        primitive: usize,
+        cull_mode: CullMode,
+        front_face: FrontFace,
+    ) -> Option<Self> {
+        let (size_x, size_y) = (width as f32, height as f32);
+        let [a, mut b, mut c] = [
+            snap_to_subpixel(homogeneous_to_screen(triangle.vertices[0], size_x, size_y)),
+            snap_to_subpixel(homogeneous_to_screen(triangle.vertices[1], size_x, size_y)),
+            snap_to_subpixel(homogeneous_to_screen(triangle.vertices[2], size_x, size_y)),
+        ];
+
+        // Face culling
+        // The area is positive if the vertices are ordered counter-clockwise on screen
+        let area = EdgeFunction::new(b, c).evaluate(a);
+        if area == 0 {
+            return None;
+        }
+
+        let front_facing = (area > 0) == (front_face == FrontFace::CounterClockwise);
+        if cull_mode.culls(front_facing) {
+            return None;
+        }
+
+        // Edge functions are positive inside of triangles with counter-clockwise order,
+        // so the order of clockwise triangles is reversed before rasterization
+        if area < 0 {
+            triangle.vertices.swap(1, 2);
+            triangle.vertex_attributes.swap(1, 2);
+            (b, c) = (c, b);
+        }
+        let area = area.abs();
+
+        let edges = [
+            EdgeFunction::new(b, c),
+            EdgeFunction::new(c, a),
+            EdgeFunction::new(a, b),
+        ];
+
+        let inverse_view_depths = 1.0
+            / Float3::new(
+                triangle.vertices[0].w,
+                triangle.vertices[1].w,
+                triangle.vertices[2].w,
+            );
+        let depths =
+            (1.0 + Float3::new(
+                triangle.vertices[0].z / triangle.vertices[0].w,
+                triangle.vertices[1].z / triangle.vertices[1].w,
+                triangle.vertices[2].z / triangle.vertices[2].w,
+            )) * 0.5;
+
+        // Determine bounding box of all pixels which may contain covered samples
+        let (min_x, min_y, max_x, max_y) = (
+            a.0.min(b.0).min(c.0),
+            a.1.min(b.1).min(c.1),
+            a.0.max(b.0).max(c.0),
+            a.1.max(b.1).max(c.1),
+        );
+        let first_pixel = |v: i64| v.div_euclid(SUBPIXEL_ONE);
+        let end_pixel = |v: i64| (v + SUBPIXEL_ONE - 1).div_euclid(SUBPIXEL_ONE);
+
+        let bbox = (
+            first_pixel(min_x).clamp(0, width as i64) as usize,
+            first_pixel(min_y).clamp(0, height as i64) as usize,
+            end_pixel(max_x).clamp(0, width as i64) as usize,
+            end_pixel(max_y).clamp(0, height as i64) as usize,
+        );
 
-    pub fn perspective_interpolation(&self, inverse_ws: Float3, w: f32, weights: Float3) -> T {
-        (self.vertex_attributes[0] * (inverse_ws.x * weights.x)
-            + self.vertex_attributes[1] * (inverse_ws.y * weights.y)
-            + self.vertex_attributes[2] * (inverse_ws.z * weights.z))
-            * w
+        Some(Self {
+            edges,
+            inverse_area: 1.0 / area as f32,
+            depths,
+            inverse_view_depths,
+            vertex_attributes: triangle.vertex_attributes,
+            bbox,
+            primitive,
+            front_facing,
+        })
     }
+
+    /// Call `fragment` for every pixel of the given tile in which the triangle covers
+    /// at least one sample of the sample pattern for `samples` samples per pixel
+    ///
+    /// With a single sample, coverage is tested at pixel centers. The edge functions are
+    /// stepped incrementally in integer arithmetic, so that adjacent triangles of a mesh
+    /// cover every sample exactly once.
+    fn for_each_fragment<F>(
+        &self,
+        tile: (usize, usize, usize, usize),
+        samples: usize,
+        mut fragment: F,
+    ) where
+        F: FnMut(&Fragment),
+    {
+        let (start_x, start_y) = (self.bbox.0.max(tile.0), self.bbox.1.max(tile.1));
+        let (end_x, end_y) = (self.bbox.2.min(tile.2), self.bbox.3.min(tile.3));
+        if start_x >= end_x || start_y >= end_y {
+            return;
+        }
+
+        // Offsets of the edge functions from the pixel center to each sample
+        let mut sample_offsets = [[0i64; 3]; MAX_SAMPLES];
+        for (offsets, &(sx, sy)) in sample_offsets.iter_mut().zip(sample_pattern(samples)) {
+            let d = (sx * SUBPIXEL_ONE / 16, sy * SUBPIXEL_ONE / 16);
+            *offsets = self.edges.map(|edge| edge.offset(d));
+        }
+        let sample_offsets = &sample_offsets[..samples];
+
+        let barycentric =
+            |w: [i64; 3]| Float3::new(w[0] as f32, w[1] as f32, w[2] as f32) * self.inverse_area;
+
+        let center = (
+            start_x as i64 * SUBPIXEL_ONE + SUBPIXEL_ONE / 2,
+            start_y as i64 * SUBPIXEL_ONE + SUBPIXEL_ONE / 2,
+        );
+        let mut row = self.edges.map(|edge| edge.evaluate(center));
+
+        for y in start_y..end_y {
+            let mut w = row;
+            for x in start_x..end_x {
+                let mut coverage = 0u32;
+                let mut sample_weights = [Float3::zeros(); MAX_SAMPLES];
+
+                for (s, offsets) in sample_offsets.iter().enumerate() {
+                    let ws = [w[0] + offsets[0], w[1] + offsets[1], w[2] + offsets[2]];
+                    if self.edges[0].inside(ws[0])
+                        && self.edges[1].inside(ws[1])
+                        && self.edges[2].inside(ws[2])
+                    {
+                        coverage |= 1 << s;
+                        sample_weights[s] = barycentric(ws);
+                    }
+                }
+
+                if coverage != 0 {
+                    let center_inside = self.edges[0].inside(w[0])
+                        && self.edges[1].inside(w[1])
+                        && self.edges[2].inside(w[2]);
+                    let weights = if center_inside {
+                        barycentric(w)
+                    } else {
+                        sample_weights[coverage.trailing_zeros() as usize]
+                    };
+
+                    fragment(&Fragment {
+                        x,
+                        y,
+                        coverage,
+                        sample_weights,
+                        weights,
+                    });
+                }
+
+                for (value, edge) in w.iter_mut().zip(self.edges.iter()) {
+                    *value += edge.step_x;
+                }
+            }
+
+            for (value, edge) in row.iter_mut().zip(self.edges.iter()) {
+                *value += edge.step_y;
+            }
+        }
+    }
+
+    /// Depth like in OpenGL
+    ///
+    /// Perspective projection leads to
+    /// z' = ((far + near) / (far - near) - 2 * far * near / (z * (far - near)) + 1) / 2
+    /// which is equivalent to (1/z - 1/near) / (1/far - 1/near) because
+    /// (I) -2/z / ((far - near) / (far * near)) = -2/z / (1/near - 1/far) = 2/z / (1/far - 1/near)
+    /// (II) (far + near) / (far - near) = (1/near + 1/far) / (1/near - 1/far)
+    /// (III) ((2/z - (1/near + 1/far)) / (1/far - 1/near) + 1)/ 2
+    ///     = (2/z - (1/near + 1/far) + (1/far - 1/near)) / (2 * (1/far - 1/near))
+    ///     = (1/z - 1/near) / (1/far - 1/near) = a * 1/z + b
+    fn depth(&self, weights: Float3) -> f32 {
+        self.depths.dot(weights)
+    }
+
+    /// Largest change of depth between neighboring pixels
+    ///
+    /// Depth is linear in screen space, so the slope is constant across the triangle.
+    fn max_depth_slope(&self) -> f32 {
+        let depths = [self.depths.x, self.depths.y, self.depths.z];
+        let (mut dx, mut dy) = (0.0, 0.0);
+        for (edge, depth) in self.edges.iter().zip(depths) {
+            dx += depth * edge.step_x as f32;
+            dy += depth * edge.step_y as f32;
+        }
+
+        (dx * self.inverse_area)
+            .abs()
+            .max((dy * self.inverse_area).abs())
+    }
+
+    /// Change of the barycentric weights to the next pixel in x- and y-direction
+    fn weight_steps(&self) -> (Float3, Float3) {
+        let step = |f: fn(&EdgeFunction) -> i64| {
+            Float3::new(
+                f(&self.edges[0]) as f32,
+                f(&self.edges[1]) as f32,
+                f(&self.edges[2]) as f32,
+            ) * self.inverse_area
+        };
+
+        (step(|edge| edge.step_x), step(|edge| edge.step_y))
+    }
+
+    /// Barycentric weights corrected for perspective, such that they interpolate linearly
+    /// in view space
+    fn perspective_weights(&self, weights: Float3) -> Float3 {
+        let inverse_ws = self.inverse_view_depths;
+        let w = 1.0 / inverse_ws.dot(weights);
+
+        inverse_ws * weights * w
+    }
+
+    /// Interpolate vertex attributes with perspective correction
+    fn perspective_interpolation(&self, weights: Float3) -> T {
+        let weights = self.perspective_weights(weights);
+
+        self.vertex_attributes[0] * weights.x
+            + self.vertex_attributes[1] * weights.y
+            + self.vertex_attributes[2] * weights.z
+    }
+}
+
+/// A rectangular region of a buffer with exclusive access to its pixels
+struct Tile<'a, T> {
+    /// Horizontal pixel coordinate of the upper left corner
+    x: usize,
+    /// Vertical pixel coordinate of the upper left corner
+    y: usize,
+    /// Number of samples stored per pixel
+    samples: usize,
+    /// Rows of pixels covered by the tile
+    rows: Vec<&'a mut [T]>,
+}
+
+impl<'a, T> Tile<'a, T> {
+    /// Pixel bounds of the tile given as `(start_x, start_y, end_x, end_y)`, where
+    /// the end is exclusive
+    fn bounds(&self) -> (usize, usize, usize, usize) {
+        let width = self.rows.first().map_or(0, |row| row.len() / self.samples);
+        (self.x, self.y, self.x + width, self.y + self.rows.len())
+    }
+
+    /// Access the samples of the pixel at screen coordinates (x, y)
+    fn pixel(&mut self, x: usize, y: usize) -> &mut [T] {
+        let offset = (x - self.x) * self.samples;
+        &mut self.rows[y - self.y][offset..offset + self.samples]
+    }
+}
+
+/// Split a buffer of the given width into tiles of at most `TILE_SIZE` x `TILE_SIZE` pixels
+///
+/// The buffer stores `samples` consecutive samples for each pixel. Tiles are returned
+/// in row-major order.
+fn split_into_tiles<T>(buffer: &mut [T], width: usize, samples: usize) -> Vec<Tile<'_, T>> {
+    let tiles_x = width.div_ceil(TILE_SIZE);
+    let mut tiles = Vec::new();
+
+    for (tile_y, band) in buffer.chunks_mut(width * samples * TILE_SIZE).enumerate() {
+        let first = tiles.len();
+        tiles.extend((0..tiles_x).map(|tile_x| Tile {
+            x: tile_x * TILE_SIZE,
+            y: tile_y * TILE_SIZE,
+            samples,
+            rows: Vec::new(),
+        }));
+
+        for row in band.chunks_mut(width * samples) {
+            for (tile_x, span) in row.chunks_mut(TILE_SIZE * samples).enumerate() {
+                tiles[first + tile_x].rows.push(span);
+            }
+        }
+    }
+
+    tiles
+}
+
+/// Sort triangles into the tiles overlapped by their bounding boxes
+///
+/// Each bin stores triangle indices in submission order, such that every pixel sees
+/// the triangles in the same order as a serial rasterizer would.
+fn bin_triangles<T>(triangles: &[RasterTriangle<T>], width: usize, height: usize) -> Vec<Vec<usize>>
+where
+    T: Debug + Clone + Copy + LinearInterpolation + Add<Output = T> + Mul<f32, Output = T>,
+{
+    let tiles_x = width.div_ceil(TILE_SIZE);
+    let tiles_y = height.div_ceil(TILE_SIZE);
+    let mut bins = vec![Vec::new(); tiles_x * tiles_y];
+
+    for (i, triangle) in triangles.iter().enumerate() {
+        let (start_x, start_y, end_x, end_y) = triangle.bbox;
+        if start_x >= end_x || start_y >= end_y {
+            continue;
+        }
+
+        for tile_y in start_y / TILE_SIZE..=(end_y - 1) / TILE_SIZE {
+            for tile_x in start_x / TILE_SIZE..=(end_x - 1) / TILE_SIZE {
+                bins[tile_y * tiles_x + tile_x].push(i);
+            }
+        }
+    }
+
+    bins
+}
+
+/// Triangles of all models queued for rasterization during render pass
+///
+/// [Model shaders](crate::shader::ModelShader) queue the triangles of their model with
+/// [draw](RenderPass::draw). The models are rasterized in the order they are drawn.
+pub struct RenderPass<'a> {
+    /// Width of the render target in pixels
+    width: usize,
+    /// Height of the render target in pixels
+    height: usize,
+    /// Triangles and pixel shaders of the drawn models
+    batches: Vec<Box<dyn RasterBatch + 'a>>,
+}
+
+impl<'a> RenderPass<'a> {
+    /// Create an empty render pass for a render target of the given size
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            batches: Vec::new(),
+        }
+    }
+
+    /// Assemble, cull, and clip (if necessary) the triangles of a model from the output
+    /// of its vertex shader and queue them for shading with the given pixel shader
+    pub fn draw<T: Varyings>(
+        &mut self,
+        model: &'a Model,
+        output: RenderPassShaderOutput<T>,
+        shader: &'a dyn PixelShader<T>,
+    ) {
+        debug_assert_eq!(
+            output.attributes.len(),
+            model.vertex_indices.len(),
+            "vertex shader must output attributes for every vertex index"
+        );
+
+        let culling_bitmasks = output
+            .vertices
+            .iter()
+            .map(culling_bitmask)
+            .collect::<Vec<_>>();
+
+        let triangles = model
+            .vertex_indices
+            .chunks_exact(3)
+            .zip(output.attributes.chunks_exact(3))
+            .enumerate()
+            .filter(|(_, (vs, _))| {
+                (culling_bitmasks[vs[0]] & culling_bitmasks[vs[1]] & culling_bitmasks[vs[2]]) == 0
+            })
+            .map(|(primitive, (vs, attrs))| {
+                let triangle = Triangle::new(
+                    [
+                        output.vertices[vs[0]],
+                        output.vertices[vs[1]],
+                        output.vertices[vs[2]],
+                    ],
+                    [attrs[0], attrs[1], attrs[2]],
+                );
+                (primitive, triangle)
+            })
+            .flat_map(|(primitive, triangle)| {
+                clip_triangle(triangle)
+                    .into_iter()
+                    .map(move |triangle| (primitive, triangle))
+            })
+            .filter_map(|(primitive, triangle)| {
+                RasterTriangle::new(
+                    triangle,
+                    self.width,
+                    self.height,
+                    primitive,
+                    model.cull_mode,
+                    model.front_face,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let bins = bin_triangles(&triangles, self.width, self.height);
+        self.batches.push(Box::new(Batch {
+            triangles,
+            bins,
+            shader,
+            material: &model.material,
+        }));
+    }
+}
+
+/// Rasterization of the triangles of a single model, hiding their attribute type
+trait RasterBatch: Sync {
+    /// Depth test and shade the triangles overlapping the tile with the given index
+    fn rasterize(
+        &self,
+        tile: usize,
+        color_tile: &mut Tile<Float3>,
+        depth_tile: &mut Tile<f32>,
+        samples: usize,
+        context: &ShadingContext,
+    );
+}
+
+/// Triangles of a model in screen space with the pixel shader and material shading them
+struct Batch<'a, T>
+where
+    T: Varyings,
+{
+    /// Triangles ready for rasterization
+    triangles: Vec<RasterTriangle<T>>,
+    /// Indices of the triangles overlapping each tile
+    bins: Vec<Vec<usize>>,
+    /// Pixel shader of the model
+    shader: &'a dyn PixelShader<T>,
+    /// Material of the model
+    material: &'a Material,
+}
+
+impl<T> RasterBatch for Batch<'_, T>
+where
+    T: Varyings,
+{
+    fn rasterize(
+        &self,
+        tile: usize,
+        color_tile: &mut Tile<Float3>,
+        depth_tile: &mut Tile<f32>,
+        samples: usize,
+        context: &ShadingContext,
+    ) {
+        for &i in self.bins[tile].iter() {
+            let triangle = &self.triangles[i];
+            let (step_x, step_y) = triangle.weight_steps();
+            let corner_uvs = match triangle.vertex_attributes.map(|attrs| attrs.uv()) {
+                [Some(uv0), Some(uv1), Some(uv2)] => Some([uv0, uv1, uv2]),
+                _ => None,
+            };
+            let interpolate_uv = |uvs: [Float2; 3], weights| {
+                let weights = triangle.perspective_weights(weights);
+                uvs[0] * weights.x + uvs[1] * weights.y + uvs[2] * weights.z
+            };
+
+            triangle.for_each_fragment(color_tile.bounds(), samples, |fragment| {
+                let depth_samples = depth_tile.pixel(fragment.x, fragment.y);
+
+                // Depth test per sample
+                let mut passed = 0u32;
+                let mut depths = [0.0; MAX_SAMPLES];
+                for s in fragment.covered_samples() {
+                    let depth = triangle.depth(fragment.sample_weights[s]);
+                    if depth > depth_samples[s] || depth > 1.0 {
+                        continue;
+                    }
+
+                    passed |= 1 << s;
+                    depths[s] = depth;
+                }
+
+                if passed == 0 {
+                    return;
+                }
+
+                // Shade once per pixel. The derivatives are the differences to the
+                // texture coordinates at the neighboring pixels, like in a 2x2 quad, where
+                // the triangle's plane is extended beyond its edges if necessary.
+                let (duv_dx, duv_dy) = match corner_uvs {
+                    Some(uvs) => {
+                        let uv = interpolate_uv(uvs, fragment.weights);
+                        (
+                            interpolate_uv(uvs, fragment.weights + step_x) - uv,
+                            interpolate_uv(uvs, fragment.weights + step_y) - uv,
+                        )
+                    }
+                    None => (Float2::zeros(), Float2::zeros()),
+                };
+                let input = PixelShaderInput {
+                    attrs: triangle.perspective_interpolation(fragment.weights),
+                    x: fragment.x,
+                    y: fragment.y,
+                    depth: triangle.depth(fragment.weights),
+                    front_facing: triangle.front_facing,
+                    primitive_id: triangle.primitive,
+                    duv_dx,
+                    duv_dy,
+                };
+                let (color, alpha) = match self.shader.shade(context, self.material, &input) {
+                    PixelShaderOutput::Color(color, alpha) => (color, alpha),
+                    PixelShaderOutput::Discard => return,
+                };
+
+                // Depth is only written for fragments which are not discarded
+                let color_samples = color_tile.pixel(fragment.x, fragment.y);
+                for s in (0..samples).filter(|s| passed & (1 << s) != 0) {
+                    depth_samples[s] = depths[s];
+                    color_samples[s] = if alpha < 1.0 {
+                        color * alpha + color_samples[s] * (1.0 - alpha)
+                    } else {
+                        color
+                    };
+                }
+            });
+        }
+    }
+}
+
+/// Process work items on up to `threads` worker threads
+///
+/// Items are handed out one at a time from a shared queue. With a single thread the
+/// items are processed on the calling thread.
+fn for_each_parallel<W, F>(threads: usize, work: Vec<W>, f: F)
+where
+    W: Send,
+    F: Fn(W) + Sync,
+{
+    if threads <= 1 {
+        work.into_iter().for_each(f);
+        return;
+    }
+
+    let queue = Mutex::new(work.into_iter());
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                loop {
+                    let next = queue.lock().unwrap().next();
+                    match next {
+                        Some(item) => f(item),
+                        None => break,
+                    }
+                }
+            });
+        }
+    });
 }
 
 /// A render target used for presenting the result of rasterization
@@ -139,15 +889,38 @@ pub struct RenderTarget {
     pub height: usize,
     /// Width and height stored as a vector
     pub size: Float2,
+    /// Number of samples per pixel used for multisample anti-aliasing
+    pub samples: usize,
     /// Color buffer in RGB format
+    ///
+    /// Contains the resolved color of each pixel after [render](RenderTarget::render).
     pub color_buffer: Vec<Float3>,
     /// Depth buffer
+    ///
+    /// Contains the closest depth of all samples in each pixel after
+    /// [render](RenderTarget::render).
     pub depth_buffer: Vec<f32>,
+    /// Colors of all samples, stored consecutively for each pixel
+    pub color_samples: Vec<Float3>,
+    /// Depths of all samples, stored consecutively for each pixel
+    pub depth_samples: Vec<f32>,
+    /// Number of threads used for rasterization
+    ///
+    /// Defaults to the available parallelism of the machine. With a value of 1
+    /// the scene is rasterized serially on the calling thread.
+    pub threads: usize,
 }
 
 impl RenderTarget {
     /// Create a new render target
-    pub fn new(width: usize, height: usize) -> Self {
+    ///
+    /// Each pixel stores `samples` color and depth samples, which must be one of 1, 2, 4,
+    /// or 8. With more than one sample, coverage and depth are tested per sample while
+    /// pixel shaders are only run once per pixel (multisample anti-aliasing).
+    pub fn new(width: usize, height: usize, samples: usize) -> Self {
+        // Validate sample count
+        sample_pattern(samples);
+
         let mut color_buffer: Vec<Float3> = Vec::new();
         color_buffer.resize(width * height, Float3::zeros());
         let mut depth_buffer: Vec<f32> = Vec::new();
@@ -157,8 +930,12 @@ impl RenderTarget {
             width,
             height,
             size: Float2::new(width as f32, height as f32),
+            samples,
             color_buffer,
             depth_buffer,
+            color_samples: vec![Float3::zeros(); width * height * samples],
+            depth_samples: vec![f32::INFINITY; width * height * samples],
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
         }
     }
 
@@ -167,268 +944,350 @@ impl RenderTarget {
     pub fn clear(&mut self, clear_color: Float3) {
         self.color_buffer.fill(clear_color);
         self.depth_buffer.fill(f32::INFINITY);
+        self.color_samples.fill(clear_color);
+        self.depth_samples.fill(f32::INFINITY);
+    }
+
+    /// Resolve the samples of each pixel into the color and depth buffer
+    ///
+    /// Colors are averaged over all samples while the depth buffer receives the
+    /// closest depth of all samples.
+    pub fn resolve(&mut self) {
+        let inverse_samples = 1.0 / self.samples as f32;
+
+        for (((color, depth), color_samples), depth_samples) in self
+            .color_buffer
+            .iter_mut()
+            .zip(self.depth_buffer.iter_mut())
+            .zip(self.color_samples.chunks_exact(self.samples))
+            .zip(self.depth_samples.chunks_exact(self.samples))
+        {
+            *color = color_samples
+                .iter()
+                .fold(Float3::zeros(), |sum, sample| sum + sample)
+                * inverse_samples;
+            *depth = depth_samples
+                .iter()
+                .fold(f32::INFINITY, |min, &d| min.min(d));
+        }
     }
 
     /// Render the provided scene
     ///
-    /// Currently, this is a two pass rendering system.
+    /// 1. Render from each light's POV to its shadow map
+    /// 2. Render from camera's POV. Pixel shaders transform the world space position
+    ///    of a fragment to a light's POV to compare to its shadow map. If depth is
+    ///    lower than in shadow map, then the fragment is in shadow.
     ///
-    /// 1. Render from spotlight's POV to a shadow map
-    /// 2. Render from camera's POV. Use vertex positions in spotlight's POV to
-    ///    compare to shadow map. If depth is lower than in shadow map, then the
-    ///    fragment is in shadow.
+    /// Both passes split their output into tiles. Triangles are binned by tile and
+    /// the tiles are rasterized in parallel on [threads](RenderTarget::threads)
+    /// threads. The scene's skybox is drawn before the models, such that translucent
+    /// models are blended over it.
+    /// Finally, the samples are [resolved](RenderTarget::resolve).
     pub fn render(&mut self, scene: &mut Scene) {
         // Two-pass render pipeline
         //
         // First render pass
-        // Render scene from lights perspective
-        for model in scene.models.iter() {
-            let model_world_matrix = model.transform.world_matrix();
-            let spotlight = scene.spotlights[0].borrow();
-            let light_view_proj_matrix: crate::math::Float4x4 =
-                spotlight.camera.projection * spotlight.camera.transform.inverse_world_matrix();
-            let spotlight_width = spotlight.shadow_map.width;
-            let spotlight_height = spotlight.shadow_map.height;
-            drop(spotlight);
-
-            // Vertex shader
-            let model_shader = ShadowPassShader::new(model_world_matrix, light_view_proj_matrix);
-            let shader_input = ShadowPassShaderInput::new(&model.vertices);
-            let out = model_shader.transform(&shader_input);
-
-            // Assemble, cull, and subdivide (if necessary) triangles
-            let triangles = model
-                .vertex_indices
-                .chunks_exact(3)
-                .filter(|vs| {
-                    (out.culling_bitmasks[vs[0]] & out.culling_bitmasks[vs[1]] & out.culling_bitmasks[vs[2]]) == 0
-                })
-                .map(|vs| {
-                    Triangle::new(
-                        [
-                            out.vertices[vs[0]],
-                            out.vertices[vs[1]],
-                            out.vertices[vs[2]],
-                        ],
-                        [
-                            EmptyAttributes(()),
-                            EmptyAttributes(()),
-                            EmptyAttributes(()),
-                        ],
-                    )
-                })
-                .flat_map(|triangle| subdivide_partial_oob_triangles(triangle));
-
-            for triangle in triangles {
-                let [a, b, c] = [
-                    homogeneous_to_screen(
-                        triangle.vertices[0],
-                        spotlight_width as f32,
-                        spotlight_height as f32,
-                    ),
-                    homogeneous_to_screen(
-                        triangle.vertices[1],
-                        spotlight_width as f32,
-                        spotlight_height as f32,
-                    ),
-                    homogeneous_to_screen(
-                        triangle.vertices[2],
-                        spotlight_width as f32,
-                        spotlight_height as f32,
-                    ),
-                ];
-
-                // Back-face culling
-                if signed_triangle_area(a, b, c) <= 0.0 {
-                    continue;
-                }
+        // Render scene from each light's perspective
+        for spotlight in scene.lights.spotlights.iter() {
+            let spotlight = &mut *spotlight.write().unwrap();
+            self.render_shadow_map(
+                &scene.models,
+                &spotlight.camera,
+                scene.time,
+                &mut spotlight.shadow_map,
+                &mut spotlight.shadow_moments,
+                spotlight.shadow_map_samples,
+                &spotlight.shadow_filter,
+                &spotlight.shadow_bias,
+            );
+            spotlight.shadow_matrix = spotlight.camera.view_proj_matrix();
+        }
 
-                let depths =
-                    (1.0 + Float3::new(
-                        triangle.vertices[0].z / triangle.vertices[0].w,
-                        triangle.vertices[1].z / triangle.vertices[1].w,
-                        triangle.vertices[2].z / triangle.vertices[2].w,
-                    )) * 0.5;
-
-                // Determine chunk bounding box
-                let (min_x, min_y, max_x, max_y) = (
-                    a.x.min(b.x).min(c.x),
-                    a.y.min(b.y).min(c.y),
-                    a.x.max(b.x).max(c.x),
-                    a.y.max(b.y).max(c.y),
+        for light in scene.lights.directional_lights.iter() {
+            let light = &mut *light.write().unwrap();
+            let active_cascades = light.active_cascades();
+            for cascade in light.cascades.iter_mut().take(active_cascades) {
+                self.render_shadow_map(
+                    &scene.models,
+                    &cascade.camera,
+                    scene.time,
+                    &mut cascade.shadow_map,
+                    &mut cascade.shadow_moments,
+                    light.shadow_map_samples,
+                    &light.shadow_filter,
+                    &light.shadow_bias,
                 );
+                cascade.shadow_matrix = cascade.camera.view_proj_matrix();
+            }
+        }
 
-                let (bbox_start_x, bbox_start_y, bbox_end_x, bbox_end_y) = (
-                    min_x.floor().clamp(0.0, spotlight_width as f32) as usize,
-                    min_y.floor().clamp(0.0, spotlight_height as f32) as usize,
-                    max_x.ceil().clamp(0.0, spotlight_width as f32) as usize,
-                    max_y.ceil().clamp(0.0, spotlight_height as f32) as usize,
+        for light in scene.lights.point_lights.iter() {
+            let light = &mut *light.write().unwrap();
+            for (((camera, shadow_map), shadow_moments), shadow_matrix) in light
+                .cameras
+                .iter()
+                .zip(light.shadow_maps.iter_mut())
+                .zip(light.shadow_moments.iter_mut())
+                .zip(light.shadow_matrices.iter_mut())
+            {
+                self.render_shadow_map(
+                    &scene.models,
+                    camera,
+                    scene.time,
+                    shadow_map,
+                    shadow_moments,
+                    light.shadow_map_samples,
+                    &light.shadow_filter,
+                    &light.shadow_bias,
                 );
+                *shadow_matrix = camera.view_proj_matrix();
+            }
+        }
 
-                for y in bbox_start_y..bbox_end_y {
-                    for x in bbox_start_x..bbox_end_x {
-                        if let Some(weights) =
-                            point_in_triangle_front_face(a, b, c, Float2::new(x as f32, y as f32))
-                        {
-                            // Depth like in OpenGL
-                            // Perspective projection leads to
-                            // z' = ((far + near) / (far - near) - 2 * far * near / (z * (far - near)) + 1) / 2
-                            // which is equivalent to (1/z - 1/near) / (1/far - 1/near) because
-                            // (I) -2/z / ((far - near) / (far * near)) = -2/z / (1/near - 1/far) = 2/z / (1/far - 1/near)
-                            // (II) (far + near) / (far - near) = (1/near + 1/far) / (1/near - 1/far)
-                            // (III) ((2/z - (1/near + 1/far)) / (1/far - 1/near) + 1)/ 2
-                            //     = (2/z - (1/near + 1/far) + (1/far - 1/near)) / (2 * (1/far - 1/near))
-                            //     = (1/z - 1/near) / (1/far - 1/near) = a * 1/z + b
-                            let depth = depths.dot(weights);
-                            if depth
-                                > scene.spotlights[0].borrow().shadow_map.image
-                                    [y * spotlight_width + x]
-                                || depth > 1.0
-                            {
-                                continue;
-                            }
+        // The skybox only writes color, so models still pass the depth test
+        if let Some(skybox) = &scene.skybox {
+            self.draw_skybox(skybox, &scene.camera);
+        }
 
-                            scene.spotlights[0].borrow_mut().shadow_map.image
-                                [y * spotlight_width + x] = depth;
-                        }
+        // Second render pass
+        // Render from main cameras perspective
+        let mut pass = RenderPass::new(self.width, self.height);
+        for model in scene.models.iter() {
+            // Vertex shader, triangle assembly, culling, and clipping
+            let shader_input = RenderPassShaderInput::new(model, &scene.camera, scene.time, false);
+            model.shader.draw(&shader_input, &mut pass);
+        }
+
+        let samples = self.samples;
+        let color_tiles = split_into_tiles(&mut self.color_samples, self.width, samples);
+        let depth_tiles = split_into_tiles(&mut self.depth_samples, self.width, samples);
+        let context = ShadingContext::new(&scene.lights, &scene.camera, scene.ambient_factor);
+
+        // Models are rasterized in order within each tile
+        for_each_parallel(
+            self.threads,
+            color_tiles
+                .into_iter()
+                .zip(depth_tiles)
+                .enumerate()
+                .collect(),
+            |(tile, (mut color_tile, mut depth_tile))| {
+                for batch in pass.batches.iter() {
+                    batch.rasterize(tile, &mut color_tile, &mut depth_tile, samples, &context);
+                }
+            },
+        );
+
+        self.resolve();
+    }
+
+    /// Draw a skybox into all samples which are not yet covered by any model
+    ///
+    /// The skybox lies at infinity, so only the color of samples whose depth is still at
+    /// the value set by [clear](RenderTarget::clear) is written. Depths are left
+    /// untouched. The skybox is sampled once per pixel in the direction of the ray
+    /// through its center.
+    fn draw_skybox(&mut self, skybox: &CubeMap<Float3>, camera: &Camera) {
+        let (width, height, samples) = (self.width, self.height, self.samples);
+        let rows = self
+            .color_samples
+            .chunks_exact_mut(width * samples)
+            .zip(self.depth_samples.chunks_exact(width * samples))
+            .enumerate()
+            .collect();
+
+        for_each_parallel(self.threads, rows, |(y, (color_row, depth_row))| {
+            for (x, (colors, depths)) in color_row
+                .chunks_exact_mut(samples)
+                .zip(depth_row.chunks_exact(samples))
+                .enumerate()
+            {
+                if depths.iter().all(|&depth| depth != f32::INFINITY) {
+                    continue;
+                }
+
+                let direction = camera.view_direction(Float2::new(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                ));
+                let color = skybox.sample(direction);
+                for (sample, &depth) in colors.iter_mut().zip(depths) {
+                    if depth == f32::INFINITY {
+                        *sample = color;
                     }
                 }
             }
-        }
+        });
+    }
 
-        for model in scene.models.iter() {
-            let model_world_matrix = model.transform.world_matrix();
-            let camera_view_proj_matrix =
-                &scene.camera.projection * scene.camera.transform.inverse_world_matrix();
-            let spotlight = scene.spotlights[0].borrow();
-            let light_view_proj_matrix: crate::math::Float4x4 =
-                spotlight.camera.projection * spotlight.camera.transform.inverse_world_matrix();
-            drop(spotlight);
-
-            // Vertex shader
-            let model_shader = RenderPassShader::new(
-                model_world_matrix,
-                camera_view_proj_matrix,
-                light_view_proj_matrix,
+    /// Render the provided models from a light camera's POV to a shadow map
+    ///
+    /// With more than one sample per texel, the texel receives the nearest depth of its
+    /// samples. Averaging would produce depths belonging to no surface at the silhouettes
+    /// of casters. If the filter [uses moments](ShadowFilter::uses_moments), these are
+    /// computed per sample, averaged and blurred, as moments can be filtered linearly.
+    ///
+    /// The constant and slope-scaled depth bias are added to the depths of all rasterized
+    /// triangles.
+    ///
+    /// Pixel shaders are not run. Fragments of models whose material has an
+    /// [alpha cutoff](Material::alpha_cutoff) are alpha tested with the model's texture
+    /// coordinates instead, such that cutouts do not cast shadows.
+    #[allow(clippy::too_many_arguments)]
+    fn render_shadow_map(
+        &self,
+        models: &[Model],
+        camera: &Camera,
+        time: f32,
+        shadow_map: &mut Texture<f32>,
+        shadow_moments: &mut Texture<Float2>,
+        shadow_samples: usize,
+        filter: &ShadowFilter,
+        bias: &ShadowBias,
+    ) {
+        let shadow_width = shadow_map.width;
+        let shadow_height = shadow_map.height;
+
+        let mut triangles = Vec::new();
+        // Material of each triangle if it is alpha tested
+        let mut alpha_tests = Vec::new();
+        for model in models.iter() {
+            // Vertex shader of the model
+            let shader_input = RenderPassShaderInput::new(model, camera, time, true);
+            let vertices = model.shader.vertices(&shader_input);
+            let culling_bitmasks = vertices.iter().map(culling_bitmask).collect::<Vec<_>>();
+
+            // Texture coordinates are only needed for the alpha test
+            let alpha_test = model
+                .material
+                .alpha_cutoff
+                .is_some()
+                .then_some(&*model.material);
+            let texture_coord = |corner: usize| match alpha_test {
+                Some(_) => model
+                    .texture_coord_indices
+                    .get(corner)
+                    .map_or(Float2::zeros(), |&i| model.texture_coords[i]),
+                None => Float2::zeros(),
+            };
+
+            // Assemble, cull, and clip (if necessary) triangles
+            triangles.extend(
+                model
+                    .vertex_indices
+                    .chunks_exact(3)
+                    .enumerate()
+                    .filter(|(_, vs)| {
+                        (culling_bitmasks[vs[0]]
+                            & culling_bitmasks[vs[1]]
+                            & culling_bitmasks[vs[2]])
+                            == 0
+                    })
+                    .map(|(primitive, vs)| {
+                        let triangle = Triangle::new(
+                            [vertices[vs[0]], vertices[vs[1]], vertices[vs[2]]],
+                            [0, 1, 2].map(|k| texture_coord(3 * primitive + k)),
+                        );
+                        (primitive, triangle)
+                    })
+                    .flat_map(|(primitive, triangle)| {
+                        clip_triangle(triangle)
+                            .into_iter()
+                            .map(move |triangle| (primitive, triangle))
+                    })
+                    .filter_map(|(primitive, triangle)| {
+                        RasterTriangle::new(
+                            triangle,
+                            shadow_width,
+                            shadow_height,
+                            primitive,
+                            model.cull_mode,
+                            model.front_face,
+                        )
+                    }),
             );
-            let shader_input = RenderPassShaderInput::new(&model.vertices, &model.normals);
-            let out = model_shader.transform(&shader_input);
-
-            // Second render pass
-            // Render from main cameras perspective
-
-            // Assemble, cull, and subdivide (if necessary) triangles
-            let triangles = model
-                .vertex_indices
-                .chunks_exact(3)
-                .zip(model.texture_coord_indices.chunks_exact(3))
-                .zip(model.normal_indices.chunks_exact(3))
-                .filter(|((vs, _), _)| {
-                    (out.culling_bitmasks[vs[0]] & out.culling_bitmasks[vs[1]] & out.culling_bitmasks[vs[2]]) == 0
-                })
-                .map(|((vs, uvs), ns)| {
-                    Triangle::new(
-                        [
-                            out.vertices[vs[0]],
-                            out.vertices[vs[1]],
-                            out.vertices[vs[2]],
-                        ],
-                        [
-                            VertexAttributes::new(
-                                out.vertices_attr[vs[0]],
-                                out.light_vertices[vs[0]],
-                                model.texture_coords[uvs[0]],
-                                out.normals[ns[0]],
-                            ),
-                            VertexAttributes::new(
-                                out.vertices_attr[vs[1]],
-                                out.light_vertices[vs[1]],
-                                model.texture_coords[uvs[1]],
-                                out.normals[ns[1]],
-                            ),
-                            VertexAttributes::new(
-                                out.vertices_attr[vs[2]],
-                                out.light_vertices[vs[2]],
-                                model.texture_coords[uvs[2]],
-                                out.normals[ns[2]],
-                            ),
-                        ],
-                    )
-                })
-                .flat_map(|triangle| subdivide_partial_oob_triangles(triangle));
-
-            for triangle in triangles {
-                let [a, b, c] = [
-                    homogeneous_to_screen(triangle.vertices[0], self.size.x, self.size.y),
-                    homogeneous_to_screen(triangle.vertices[1], self.size.x, self.size.y),
-                    homogeneous_to_screen(triangle.vertices[2], self.size.x, self.size.y),
-                ];
-
-                // Back-face culling
-                if signed_triangle_area(a, b, c) <= 0.0 {
-                    continue;
-                }
+            alpha_tests.resize(triangles.len(), alpha_test);
+        }
 
-                let inverse_view_depths = 1.0
-                    / Float3::new(
-                        triangle.vertices[0].w,
-                        triangle.vertices[1].w,
-                        triangle.vertices[2].w,
-                    );
-                let depths =
-                    (1.0 + Float3::new(
-                        triangle.vertices[0].z / triangle.vertices[0].w,
-                        triangle.vertices[1].z / triangle.vertices[1].w,
-                        triangle.vertices[2].z / triangle.vertices[2].w,
-                    )) * 0.5;
-
-                // Determine chunk bounding box
-                let (min_x, min_y, max_x, max_y) = (
-                    a.x.min(b.x).min(c.x),
-                    a.y.min(b.y).min(c.y),
-                    a.x.max(b.x).max(c.x),
-                    a.y.max(b.y).max(c.y),
-                );
+        let mut shadow_depths = vec![1.0f32; shadow_width * shadow_height * shadow_samples];
+        let bins = bin_triangles(&triangles, shadow_width, shadow_height);
+        let tiles = split_into_tiles(&mut shadow_depths, shadow_width, shadow_samples);
 
-                let (bbox_start_x, bbox_start_y, bbox_end_x, bbox_end_y) = (
-                    min_x.floor().clamp(0.0, self.size.x) as usize,
-                    min_y.floor().clamp(0.0, self.size.y) as usize,
-                    max_x.ceil().clamp(0.0, self.size.x) as usize,
-                    max_y.ceil().clamp(0.0, self.size.y) as usize,
-                );
+        for_each_parallel(
+            self.threads,
+            tiles.into_iter().zip(bins).collect(),
+            |(mut tile, bin)| {
+                for &i in bin.iter() {
+                    let triangle = &triangles[i];
+                    let depth_bias = bias.constant + bias.slope_scale * triangle.max_depth_slope();
+                    triangle.for_each_fragment(tile.bounds(), shadow_samples, |fragment| {
+                        // Fragments discarded by the alpha test cast no shadow
+                        if let Some(material) = alpha_tests[i] {
+                            let uv = triangle.perspective_interpolation(fragment.weights);
+                            let alpha = material.alpha(uv.into());
+                            if material.alpha_cutoff.is_some_and(|cutoff| alpha < cutoff) {
+                                return;
+                            }
+                        }
 
-                for y in bbox_start_y..bbox_end_y {
-                    for x in bbox_start_x..bbox_end_x {
-                        if let Some(weights) =
-                            point_in_triangle_front_face(a, b, c, Float2::new(x as f32, y as f32))
-                        {
-                            // Depth like in OpenGL
-                            // Perspective projection leads to
-                            // z' = ((far + near) / (far - near) - 2 * far * near / (z * (far - near)) + 1) / 2
-                            // which is equivalent to (1/z - 1/near) / (1/far - 1/near) because
-                            // (I) -2/z / ((far - near) / (far * near)) = -2/z / (1/near - 1/far) = 2/z / (1/far - 1/near)
-                            // (II) (far + near) / (far - near) = (1/near + 1/far) / (1/near - 1/far)
-                            // (III) ((2/z - (1/near + 1/far)) / (1/far - 1/near) + 1)/ 2
-                            //     = (2/z - (1/near + 1/far) + (1/far - 1/near)) / (2 * (1/far - 1/near))
-                            //     = (1/z - 1/near) / (1/far - 1/near) = a * 1/z + b
-                            let depth = depths.dot(weights);
-                            if depth > self.depth_buffer[y * self.width + x] || depth > 1.0 {
+                        let shadow_depths = tile.pixel(fragment.x, fragment.y);
+                        for s in fragment.covered_samples() {
+                            let depth = triangle.depth(fragment.sample_weights[s]);
+                            if depth > 1.0 {
                                 continue;
                             }
 
-                            let attrs = triangle.perspective_interpolation(
-                                inverse_view_depths,
-                                1.0 / inverse_view_depths.dot(weights),
-                                weights,
-                            );
+                            let depth = (depth + depth_bias).min(1.0);
+                            if depth > shadow_depths[s] {
+                                continue;
+                            }
 
-                            self.color_buffer[y * self.width + x] = model.shader.color(attrs);
-                            self.depth_buffer[y * self.width + x] = depth;
+                            shadow_depths[s] = depth;
                         }
-                    }
+                    });
                 }
-            }
+            },
+        );
+
+        // Resolve shadow map samples to the nearest occluder
+        for (depth, samples) in shadow_map
+            .image
+            .iter_mut()
+            .zip(shadow_depths.chunks_exact(shadow_samples))
+        {
+            *depth = samples.iter().fold(1.0, |min, &d| min.min(d));
         }
+
+        // Moments for variance and exponential shadow maps
+        let blur_radius = match *filter {
+            ShadowFilter::Variance { blur_radius, .. }
+            | ShadowFilter::Exponential { blur_radius, .. } => blur_radius,
+            _ => return,
+        };
+
+        if shadow_moments.width != shadow_width || shadow_moments.height != shadow_height {
+            *shadow_moments = Texture::new(shadow_width, shadow_height);
+        }
+
+        for (moments, samples) in shadow_moments
+            .image
+            .iter_mut()
+            .zip(shadow_depths.chunks_exact(shadow_samples))
+        {
+            *moments = samples
+                .iter()
+                .map(|&depth| shadow_map_moments(camera, filter, depth))
+                .fold(Float2::zeros(), |sum, m| sum + m)
+                / shadow_samples as f32;
+        }
+
+        gaussian_blur(
+            &mut shadow_moments.image,
+            shadow_width,
+            shadow_height,
+            blur_radius,
+        );
     }
 }
 
@@ -499,102 +1358,129 @@ fn homogeneous_to_screen(vertex: Float4, width: f32, height: f32) -> Float2 {
     )
 }
 
-fn subdivide_partial_oob_triangles<T>(triangle: Triangle<T>) -> Vec<Triangle<T>>
+/// Minimal distance of clipped vertices to the plane w = 0, avoiding divisions by zero
+/// during the perspective divide
+const W_EPSILON: f32 = 1e-5;
+
+/// Signed distance of a vertex to a clip plane, non-negative if the vertex is inside
+///
+/// Planes are indexed by the bits of the culling bitmask computed by the vertex shaders.
+/// Observe that vertices in front of the camera have negative w.
+fn clip_plane_distance(vertex: &Float4, plane: u8) -> f32 {
+    match plane {
+        0 => vertex.z - vertex.w,
+        1 => -(vertex.z + vertex.w),
+        2 => vertex.y - vertex.w,
+        3 => -(vertex.y + vertex.w),
+        4 => vertex.x - vertex.w,
+        5 => -(vertex.x + vertex.w),
+        _ => -vertex.w - W_EPSILON,
+    }
+}
+
+/// Clip a triangle in homogeneous clip space against the view frustum
+///
+/// Uses the Sutherland–Hodgman algorithm to clip the triangle successively against
+/// every plane that at least one of its vertices lies outside of. The resulting convex
+/// polygon is returned as a triangle fan preserving the winding of the input triangle.
+/// Vertex attributes of new vertices are linearly interpolated in clip space.
+fn clip_triangle<T>(triangle: Triangle<T>) -> Vec<Triangle<T>>
 where
     T: Debug + Clone + Copy + LinearInterpolation + Add<Output = T> + Mul<f32, Output = T>,
 {
-    let vertices = triangle.vertices;
-
-    // Near plane clipping
-    let clip_0 = vertices[0].w >= 0.0 || vertices[0].z + vertices[0].w >= 0.0;
-    let clip_1 = vertices[1].w >= 0.0 || vertices[1].z + vertices[1].w >= 0.0;
-    let clip_2 = vertices[2].w >= 0.0 || vertices[2].z + vertices[2].w >= 0.0;
-    let clip_count = clip_0 as usize + clip_1 as usize + clip_2 as usize;
-
-    match clip_count {
-        0 => Vec::from([triangle]),
-        1 => {
-            // Determine which vertex will be clipped and which two will remain.
-            let idx_clip = if clip_0 {
-                0
-            } else {
-                if clip_1 { 1 } else { 2 }
-            };
-            let idx_next = (idx_clip + 1) % 3;
-            let idx_prev = (idx_clip - 1 + 3) % 3;
-            let vertex_clip = vertices[idx_clip];
-            let vertex_a = vertices[idx_next];
-            let vertex_b = vertices[idx_prev];
-
-            // Fraction along triangle edge at which the depth is equal to the clip distance
-            let frac_a = (vertex_clip.w + vertex_clip.z)
-                / ((vertex_clip.w + vertex_clip.z) - (vertex_a.w + vertex_a.z));
-            let frac_b = (vertex_clip.w + vertex_clip.z)
-                / ((vertex_clip.w + vertex_clip.z) - (vertex_b.w + vertex_b.z));
-
-            // New triangle points in view space
-            let clip_vertex_along_edge_a = vertex_clip.lerp(vertex_a, frac_a);
-            let clip_vertex_along_edge_b = vertex_clip.lerp(vertex_b, frac_b);
-
-            let attrs_a = triangle.vertex_attributes[idx_clip]
-                .lerp(&triangle.vertex_attributes[idx_next], frac_a);
-            let attrs_b = triangle.vertex_attributes[idx_clip]
-                .lerp(&triangle.vertex_attributes[idx_prev], frac_b);
-
-            // First new triangle
-            Vec::from([
-                Triangle::new(
-                    [clip_vertex_along_edge_b, clip_vertex_along_edge_a, vertex_b],
-                    [attrs_b, attrs_a, triangle.vertex_attributes[idx_prev]],
-                ),
-                Triangle::new(
-                    [clip_vertex_along_edge_a, vertex_a, vertex_b],
-                    [
-                        attrs_a,
-                        triangle.vertex_attributes[idx_next],
-                        triangle.vertex_attributes[idx_prev],
-                    ],
-                ),
-            ])
+    let outside = triangle
+        .vertices
+        .iter()
+        .fold(0, |mask, vertex| mask | culling_bitmask(vertex));
+
+    if outside == 0 {
+        return Vec::from([triangle]);
+    }
+
+    let mut polygon = triangle
+        .vertices
+        .into_iter()
+        .zip(triangle.vertex_attributes)
+        .collect::<Vec<_>>();
+
+    for plane in (0..7).filter(|plane| outside & (1 << plane) != 0) {
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+
+        for (i, &(vertex, attrs)) in polygon.iter().enumerate() {
+            let (next_vertex, next_attrs) = polygon[(i + 1) % polygon.len()];
+            let distance = clip_plane_distance(&vertex, plane);
+            let next_distance = clip_plane_distance(&next_vertex, plane);
+
+            if distance >= 0.0 {
+                clipped.push((vertex, attrs));
+            }
+
+            if (distance >= 0.0) != (next_distance >= 0.0) {
+                // Fraction along polygon edge at which it intersects the clip plane
+                let frac = distance / (distance - next_distance);
+                clipped.push((
+                    vertex.lerp(next_vertex, frac),
+                    attrs.lerp(&next_attrs, frac),
+                ));
+            }
         }
-        2 => {
-            // Figure out which point remains and the two that will be clipped
-            let idx_non_clip = if !clip_0 {
-                0
-            } else {
-                if !clip_1 { 1 } else { 2 }
-            };
-            let idx_next = (idx_non_clip + 1) % 3;
-            let idx_prev = (idx_non_clip - 1 + 3) % 3;
-            let vertex_non_clip = vertices[idx_non_clip];
-            let vertex_a = vertices[idx_next];
-            let vertex_b = vertices[idx_prev];
-
-            // Fraction along triangle edge at which the depth is equal to the clip distance
-            let frac_a = (vertex_non_clip.w + vertex_non_clip.z)
-                / ((vertex_non_clip.w + vertex_non_clip.z) - (vertex_a.w + vertex_a.z));
-            let frac_b = (vertex_non_clip.w + vertex_non_clip.z)
-                / ((vertex_non_clip.w + vertex_non_clip.z) - (vertex_b.w + vertex_b.z));
-
-            // New triangle points in view space
-            let clip_vertex_along_edge_a = vertex_non_clip.lerp(vertex_a, frac_a);
-            let clip_vertex_along_edge_b = vertex_non_clip.lerp(vertex_b, frac_b);
-
-            let attrs_a = triangle.vertex_attributes[idx_non_clip]
-                .lerp(&triangle.vertex_attributes[idx_next], frac_a);
-            let attrs_b = triangle.vertex_attributes[idx_non_clip]
-                .lerp(&triangle.vertex_attributes[idx_prev], frac_b);
-
-            // New triangle
-            Vec::from([Triangle::new(
-                [
-                    clip_vertex_along_edge_b,
-                    vertex_non_clip,
-                    clip_vertex_along_edge_a,
-                ],
-                [attrs_b, triangle.vertex_attributes[idx_non_clip], attrs_a],
-            )])
-        }
-        _ => Vec::new(),
+
+        if clipped.len() < 3 {
+            return Vec::new();
+        }
+        polygon = clipped;
+    }
+
+    // Triangulate convex polygon as a triangle fan around the first vertex
+    let (first_vertex, first_attrs) = polygon[0];
+    polygon
+        .windows(2)
+        .skip(1)
+        .map(|edge| {
+            Triangle::new(
+                [first_vertex, edge[0].0, edge[1].0],
+                [first_attrs, edge[0].1, edge[1].1],
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skybox_only_covers_samples_at_infinity() {
+        let (clear_color, sky_color) = (Float3::new(1.0, 0.0, 0.0), Float3::new(0.0, 1.0, 0.0));
+        let mut target = RenderTarget::new(2, 1, 2);
+        target.threads = 1;
+        target.clear(clear_color);
+        target.depth_samples[0] = 0.5;
+        target.depth_samples[3] = 0.25;
+
+        let skybox = CubeMap::new(std::array::from_fn(|_| {
+            let mut face = Texture::new(1, 1);
+            face.image[0] = sky_color;
+            face
+        }));
+        let camera = Camera::new(
+            Float3::zeros(),
+            Float3::new(0.0, 0.0, -1.0),
+            Float3::new(0.0, 1.0, 0.0),
+            60f32.to_radians(),
+            2.0,
+            -1.0,
+            -10.0,
+        );
+        target.draw_skybox(&skybox, &camera);
+
+        assert_eq!(
+            target.color_samples,
+            vec![clear_color, sky_color, sky_color, clear_color]
+        );
+        assert_eq!(
+            target.depth_samples,
+            vec![0.5, f32::INFINITY, f32::INFINITY, 0.25]
+        );
     }
 }
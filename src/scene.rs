@@ -1,14 +1,17 @@
 use crate::camera::Camera;
-use crate::light::SpotLight;
-use crate::math::Float3;
+use crate::light::{
+    DirectionalLight, EnvironmentLight, Lights, PointLight, ShadowFilter, ShadowKernel, SpotLight,
+};
+use crate::material::Material;
+use crate::math::{Float3, Float4};
 use crate::model::{Model, read_obj_file};
 use crate::render::RenderTarget;
-use crate::shader::DiffuseShaderWithSpotlight;
+use crate::shader::{BlinnPhongShader, DiffuseShaderWithSpotlight, PbrShader};
+use crate::texture::{CubeMap, Texture};
 use crate::transform::Transform;
 use raylib::RaylibHandle;
 use raylib::ffi::KeyboardKey;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::{Arc, RwLock};
 
 /// Description of the rendered scene
 pub struct Scene {
@@ -16,8 +19,14 @@ pub struct Scene {
     pub camera: Camera,
     /// Triangle models
     pub models: Vec<Model>,
-    /// Spotlights
-    pub spotlights: Vec<Rc<RefCell<SpotLight>>>,
+    /// Lights
+    pub lights: Lights,
+    /// Intensity of ambient light, used if there is no environment light
+    pub ambient_factor: f32,
+    /// Environment drawn behind all models
+    pub skybox: Option<CubeMap<Float3>>,
+    /// Time in seconds since the scene was created
+    pub time: f32,
     total_frame_time: f32,
     /// Average time necessary to compute a frame within the last second
     pub average_frame_time: f32,
@@ -40,23 +49,69 @@ impl Scene {
                 -50.0,
             ),
             models: Vec::new(),
-            spotlights: Vec::new(),
+            lights: Lights::default(),
+            ambient_factor: 0.1,
+            skybox: None,
+            time: 0.0,
             total_frame_time: 0.0,
             average_frame_time: 0.0,
             frame_counter: 0,
             last_frame_counter: 0,
         };
 
-        let ambient_factor = 0.5f32;
-        let direction_to_light = Float3::new(1.0, 1.0, 0.0).normalized();
-        let spotlight = Rc::new(RefCell::new(SpotLight::new(
-            Float3::new(1.0, 1.0, 1.0),
+        let mut spotlight = SpotLight::new(
+            Float3::new(3.0, 3.0, 3.0),
             Float3::new(-8.0, 8.0, 0.0),
             Float3::new(0.0, 0.0, 0.0),
             30f32.to_radians(),
             256,
             256,
-        )));
+        );
+        spotlight.shadow_filter = ShadowFilter::Pcss {
+            kernel: ShadowKernel::PoissonDisk,
+            taps: 16,
+            light_size: 0.5,
+        };
+        scene
+            .lights
+            .spotlights
+            .push(Arc::new(RwLock::new(spotlight)));
+
+        let spotlight = SpotLight::new(
+            Float3::new(3.0, 2.4, 1.5),
+            Float3::new(6.0, 10.0, 6.0),
+            Float3::new(0.0, 2.0, 0.0),
+            25f32.to_radians(),
+            256,
+            256,
+        );
+        scene
+            .lights
+            .spotlights
+            .push(Arc::new(RwLock::new(spotlight)));
+
+        let directional_light = DirectionalLight::new(
+            Float3::new(0.2, 0.2, 0.2),
+            Float3::new(-1.0, -1.0, 0.0),
+            3,
+            512,
+            512,
+        );
+        scene
+            .lights
+            .directional_lights
+            .push(Arc::new(RwLock::new(directional_light)));
+
+        let point_light = PointLight::new(
+            Float3::new(1.0, 0.9, 0.7),
+            Float3::new(3.0, 3.0, 4.0),
+            Float3::new(1.0, 0.09, 0.032),
+            128,
+        );
+        scene
+            .lights
+            .point_lights
+            .push(Arc::new(RwLock::new(point_light)));
 
         let (
             vertices,
@@ -95,12 +150,9 @@ impl Scene {
 
         let transform = Transform::new(0.0, 0.0, 0.0, Float3::new(0.0, 4.0, 0.0), Float3::ones());
 
-        let shader = DiffuseShaderWithSpotlight::new(
-            Float3::new(0.0, 1.0, 0.0),
-            direction_to_light,
-            ambient_factor,
-            Rc::clone(&spotlight),
-        );
+        let mut material = Material::new(Float3::new(0.0, 1.0, 0.0));
+        material.specular_color = Float3::new(1.0, 1.0, 1.0);
+        material.shininess = 32.0;
 
         scene.models.push(Model::new(
             vertices,
@@ -112,7 +164,8 @@ impl Scene {
             smooth_normals,
             smooth_normal_indices,
             transform,
-            Box::new(shader),
+            Box::new(BlinnPhongShader),
+            Arc::new(material),
         ));
 
         let (
@@ -126,12 +179,8 @@ impl Scene {
 
         let transform = Transform::new(0.0, 0.0, 0.0, Float3::new(-3.0, 1.0, 0.0), Float3::ones());
 
-        let shader = DiffuseShaderWithSpotlight::new(
-            Float3::new(1.0, 0.0, 0.0),
-            direction_to_light,
-            ambient_factor,
-            Rc::clone(&spotlight),
-        );
+        let mut material = Material::new(Float3::new(1.0, 0.0, 0.0));
+        material.roughness = 0.4;
 
         scene.models.push(Model::new(
             vertices,
@@ -141,7 +190,8 @@ impl Scene {
             normals,
             normal_indices,
             transform,
-            Box::new(shader),
+            Box::new(PbrShader),
+            Arc::new(material),
         ));
 
         let (
@@ -161,12 +211,7 @@ impl Scene {
             0.1 * Float3::ones(),
         );
 
-        let shader = DiffuseShaderWithSpotlight::new(
-            Float3::new(1.0, 1.0, 1.0),
-            direction_to_light,
-            ambient_factor,
-            Rc::clone(&spotlight),
-        );
+        let material = Material::new(Float3::new(1.0, 1.0, 1.0));
 
         scene.models.push(Model::new(
             vertices,
@@ -176,7 +221,8 @@ impl Scene {
             normals,
             normal_indices,
             transform,
-            Box::new(shader),
+            Box::new(DiffuseShaderWithSpotlight),
+            Arc::new(material),
         ));
 
         let (
@@ -190,14 +236,10 @@ impl Scene {
 
         let transform = Transform::new(0.0, 0.0, 0.0, Float3::new(0.0, 0.0, 0.0), Float3::ones());
 
-        // let texture = Texture::from_png("models/checker-map_tho.png");
-        // let shader = TextureShader::new(texture);
-        let shader = DiffuseShaderWithSpotlight::new(
-            Float3::new(0.0, 0.0, 1.0),
-            direction_to_light,
-            ambient_factor,
-            Rc::clone(&spotlight),
-        );
+        // let mut material = Material::new(Float3::ones());
+        // material.base_color_texture =
+        //     Some(Texture::<Float4>::from_png("models/checker-map_tho.png").unwrap());
+        let material = Material::new(Float3::new(0.0, 0.0, 1.0));
 
         scene.models.push(Model::new(
             vertices,
@@ -207,18 +249,59 @@ impl Scene {
             normals,
             normal_indices,
             transform,
-            Box::new(shader),
+            Box::new(DiffuseShaderWithSpotlight),
+            Arc::new(material),
         ));
-        scene.spotlights.push(spotlight);
+
+        let sky = CubeMap::from_equirectangular(&sky_gradient(64), 256);
+        // The sky is dimmed as ambient light, such that the lights still dominate
+        let mut environment = EnvironmentLight::new(&sky, 64, 5);
+        environment.intensity = 0.25;
+        scene.lights.environment = Some(Arc::new(environment));
+        scene.skybox = Some(sky);
+
+        scene.fit_shadow_cascades();
 
         scene
     }
 
+    /// Fit the shadow map cascades of all directional lights to the main camera
+    ///
+    /// The [bounds](Scene::bounds) of the models are recomputed, such that moved models
+    /// still cast shadows. They only contain the models' vertices, so geometry displaced
+    /// by a vertex shader may leave the cascades and not cast shadows.
+    pub fn fit_shadow_cascades(&mut self) {
+        let (min, max) = self.bounds();
+        for light in self.lights.directional_lights.iter() {
+            light
+                .write()
+                .unwrap()
+                .fit_to_frustum(&self.camera, min, max);
+        }
+    }
+
+    /// Axis-aligned bounding box of all models in world space
+    pub fn bounds(&self) -> (Float3, Float3) {
+        let mut min = Float3::ones() * f32::INFINITY;
+        let mut max = Float3::ones() * f32::NEG_INFINITY;
+        for model in self.models.iter() {
+            let world_matrix = model.transform.world_matrix();
+            for v in model.vertices.iter() {
+                let v = (world_matrix * Float4::from_point(*v)).xyz();
+                min = Float3::new(min.x.min(v.x), min.y.min(v.y), min.z.min(v.z));
+                max = Float3::new(max.x.max(v.x), max.y.max(v.y), max.z.max(v.z));
+            }
+        }
+
+        (min, max)
+    }
+
     /// Update the scene
     /// 
     /// In this function animations, model and camera movement are handled.
     pub fn update(&mut self, target: &RenderTarget, rl: &RaylibHandle) {
         let delta_time = rl.get_frame_time();
+        self.time += delta_time;
         self.total_frame_time += delta_time;
         self.frame_counter += 1;
         if self.total_frame_time > 1.0 {
@@ -258,5 +341,30 @@ impl Scene {
 
         cam_transform.position += move_delta.normalized() * CAM_SPEED * delta_time;
         cam_transform.position.y = 2.0;
+
+        self.fit_shadow_cascades();
+    }
+}
+
+/// Sky fading from a bright horizon to a blue zenith above a dark ground
+///
+/// The sky is given as an image with an equirectangular projection, which only varies
+/// with latitude.
+fn sky_gradient(height: usize) -> Texture<Float3> {
+    let zenith = Float3::new(0.25, 0.45, 0.85);
+    let horizon = Float3::new(0.75, 0.8, 0.85);
+    let ground = Float3::new(0.2, 0.18, 0.16);
+
+    let mut texture = Texture::new(1, height);
+    for (y, texel) in texture.image.iter_mut().enumerate() {
+        // Elevation from 1 at the top row (zenith) to -1 at the bottom row (nadir)
+        let elevation = 1.0 - 2.0 * (y as f32 + 0.5) / height as f32;
+        *texel = if elevation >= 0.0 {
+            horizon.lerp(zenith, elevation)
+        } else {
+            ground
+        };
     }
+
+    texture
 }
@@ -1,12 +1,27 @@
-use crate::light::SpotLight;
-use crate::math::{Float3, Float4, Float4x4};
-use crate::render::VertexAttributes;
-use crate::texture::Texture;
-use rand::distr::{Distribution, Uniform};
-use std::cell::RefCell;
-use std::rc::Rc;
-
-fn culling_bitmask(vertex: &Float4) -> u8 {
+use crate::camera::{Camera, ProjectionType};
+use crate::light::{Lights, ShadowFilter, ShadowKernel};
+use crate::material::Material;
+use crate::math::{Float2, Float3, Float4, Float4x4};
+use crate::model::Model;
+use crate::postprocess::sample_bilinear;
+use crate::render::{RenderPass, Varyings, VertexAttributes};
+use crate::texture::{Texture, TextureCoordinates};
+use std::marker::PhantomData;
+
+/// Compute a bitmask describing which clip planes a homogeneous vertex lies outside of
+///
+/// Stores 7 bits for culling:
+/// - Bit 0 is 1 if `vertex.z - vertex.w <= 0.0`
+/// - Bit 1 is 1 if `vertex.z + vertex.w >= 0.0`
+/// - Bit 2 is 1 if `vertex.y - vertex.w <= 0.0`
+/// - Bit 3 is 1 if `vertex.y + vertex.w >= 0.0`
+/// - Bit 4 is 1 if `vertex.x - vertex.w <= 0.0`
+/// - Bit 5 is 1 if `vertex.x + vertex.w >= 0.0`
+/// - Bit 6 is 1 if `vertex.w >= 0.0`
+///
+/// A triangle is culled if the bitmasks cb0, cb1, cb2 of the three vertices
+/// fulfill `cb0 & cb1 & cb2 > 0`.
+pub(crate) fn culling_bitmask(vertex: &Float4) -> u8 {
     (((vertex.w >= 0.0) as u8) << 6)
         + (((vertex.x + vertex.w >= 0.0) as u8) << 5)
         + (((vertex.x - vertex.w <= 0.0) as u8) << 4)
@@ -20,229 +35,434 @@ pub trait VertexShader<I, O> {
     fn transform(&self, input: &I) -> O;
 }
 
-/// Vertex shader used on each model during shadow pass
-pub struct ShadowPassShader {
+/// Vertex shader used on each model during render pass
+///
+/// Outputs the [attributes](VertexAttributes) used by the built-in pixel shaders.
+pub struct RenderPassShader;
+
+/// Input to the vertex shader of a model
+///
+/// The same vertex shader is run in the shadow pass from each light's POV and in the
+/// main pass from the camera's POV, so displaced vertices cast matching shadows.
+pub struct RenderPassShaderInput<'a> {
+    /// Model whose vertices are transformed
+    pub model: &'a Model,
     /// Homogeneous matrix describing the transformation from model to world space
     pub model_world_matrix: Float4x4,
-    /// Homogeneous matrix describing the transformation from world space to the
-    /// light's projected view space
-    pub light_view_proj_matrix: Float4x4,
-}
-
-/// Input to the shadow pass vertex shader
-pub struct ShadowPassShaderInput<'a> {
-    /// Vertices in model space
-    pub vertices: &'a Vec<Float3>,
-}
-
-impl<'a> ShadowPassShaderInput<'a> {
-    /// Create a new input to the shadow pass vertex shader
-    pub fn new(vertices: &'a Vec<Float3>) -> Self {
-        Self { vertices }
-    }
+    /// Homogeneous matrix describing the transformation from world to view space
+    pub view_matrix: Float4x4,
+    /// Homogeneous matrix describing the transformation from view to clip space
+    pub projection_matrix: Float4x4,
+    /// Position of the camera (or light) in world space
+    pub camera_position: Float3,
+    /// Time in seconds since the scene was created, used for vertex animation
+    pub time: f32,
+    /// Whether only the clip space vertices are used (shadow pass), such that vertex
+    /// shaders may skip computing the attributes
+    pub depth_only: bool,
 }
 
-/// Output from the shadow pass vertex shader
-pub struct ShadowPassShaderOutput {
-    /// Transformed homogeneous vertices in light's clip space
-    pub vertices: Vec<Float4>,
-    /// Bit-masks for culling of triangles, stored by vertex
-    ///
-    /// Stores 7 bits for culling:
-    /// - Bit 0 is 1 if `vertex.z - vertex.w <= 0.0`
-    /// - Bit 1 is 1 if `vertex.z + vertex.w >= 0.0`
-    /// - Bit 2 is 1 if `vertex.y - vertex.w <= 0.0`
-    /// - Bit 3 is 1 if `vertex.y + vertex.w >= 0.0`
-    /// - Bit 4 is 1 if `vertex.x - vertex.w <= 0.0`
-    /// - Bit 5 is 1 if `vertex.x + vertex.w >= 0.0`
-    /// - Bit 6 is 1 if `vertex.w >= 0.0`
-    ///
-    /// A triangle is culled if the bitmasks cb0, cb1, cb2 of the three vertices
-    /// fulfill `cb0 & cb1 & cb2 > 0`.
-    pub culling_bitmasks: Vec<u8>,
-}
-
-impl ShadowPassShader {
-    /// Create a new shadow pass vertex shader
-    pub fn new(model_world_matrix: Float4x4, light_view_proj_matrix: Float4x4) -> Self {
+impl<'a> RenderPassShaderInput<'a> {
+    /// Create a new input to the vertex shader rendering a model from a camera's POV
+    pub fn new(model: &'a Model, camera: &Camera, time: f32, depth_only: bool) -> Self {
         Self {
-            model_world_matrix,
-            light_view_proj_matrix,
+            model,
+            model_world_matrix: model.transform.world_matrix(),
+            view_matrix: camera.transform.inverse_world_matrix(),
+            projection_matrix: camera.projection,
+            camera_position: camera.transform.position,
+            time,
+            depth_only,
         }
     }
-}
 
-impl<'a> VertexShader<ShadowPassShaderInput<'a>, ShadowPassShaderOutput> for ShadowPassShader {
-    /// Apply vertex shader to vertices in model space
-    fn transform(&self, input: &ShadowPassShaderInput<'a>) -> ShadowPassShaderOutput {
-        let transformation = self.light_view_proj_matrix * self.model_world_matrix;
-
-        let vertices = input
-            .vertices
-            .iter()
-            .map(|v| &transformation * Float4::from_point(*v))
-            .collect::<Vec<_>>();
-
-        let culling_bitmasks = vertices
-            .iter()
-            .map(|v| culling_bitmask(&v))
-            .collect::<Vec<_>>();
-
-        ShadowPassShaderOutput {
-            vertices,
-            culling_bitmasks,
-        }
+    /// Homogeneous matrix describing the transformation from world to clip space
+    pub fn view_proj_matrix(&self) -> Float4x4 {
+        self.projection_matrix * self.view_matrix
     }
 }
 
-/// Vertex shader used on each model during render pass
-pub struct RenderPassShader {
-    /// Homogeneous matrix describing the transformation from model to world space
-    pub model_world_matrix: Float4x4,
-    /// Homogeneous matrix describing the transformation from world space to the
-    /// camera's projected view space
-    pub camera_view_proj_matrix: Float4x4,
-    /// Homogeneous matrix describing the transformation from world space to the
-    /// light's projected view space
-    pub light_view_proj_matrix: Float4x4,
-}
-
-/// Input to the render pass vertex shader
-pub struct RenderPassShaderInput<'a, 'b> {
-    /// Vertices in model space
-    pub vertices: &'a Vec<Float3>,
-    /// Normals in model space
-    pub normals: &'b Vec<Float3>,
-}
-
-impl<'a, 'b> RenderPassShaderInput<'a, 'b> {
-    /// Create a new input to the render pass vertex shader
-    pub fn new(vertices: &'a Vec<Float3>, normals: &'b Vec<Float3>) -> Self {
-        Self { vertices, normals }
-    }
-}
-
-/// Output of the render pass vertex shader
-pub struct RenderPassShaderOutput {
+/// Output of a render pass vertex shader
+///
+/// Triangles are assembled from the model's [vertex indices](Model::vertex_indices).
+/// Triangles completely outside of the view frustum are culled and the others are
+/// clipped, so the attributes at their corners are interpolated before the triangles
+/// are rasterized.
+pub struct RenderPassShaderOutput<T> {
     /// Transformed homogeneous vertices in camera's clip space
     pub vertices: Vec<Float4>,
-    /// Bit-masks for culling of triangles, stored by vertex
-    ///
-    /// Stores 7 bits for culling:
-    /// - Bit 0 is 1 if `vertex.z - vertex.w <= 0.0`
-    /// - Bit 1 is 1 if `vertex.z + vertex.w >= 0.0`
-    /// - Bit 2 is 1 if `vertex.y - vertex.w <= 0.0`
-    /// - Bit 3 is 1 if `vertex.y + vertex.w >= 0.0`
-    /// - Bit 4 is 1 if `vertex.x - vertex.w <= 0.0`
-    /// - Bit 5 is 1 if `vertex.x + vertex.w >= 0.0`
-    /// - Bit 6 is 1 if `vertex.w >= 0.0`
+    /// Attributes at the corners of the triangles in groups of 3 for each triangle
     ///
-    /// A triangle is culled if the bitmasks cb0, cb1, cb2 of the three vertices
-    /// fulfill `cb0 & cb1 & cb2 > 0`.
-    pub culling_bitmasks: Vec<u8>,
-    /// Transformed homogeneous vertices in light's clip space
-    pub light_vertices: Vec<Float4>,
-    /// Transformed vertices in world space
-    pub vertices_attr: Vec<Float3>,
-    /// Transformed normals rotated to world space
-    pub normals: Vec<Float3>,
-}
-
-impl RenderPassShader {
-    /// Create a new render pass vertex shader
-    pub fn new(
-        model_world_matrix: Float4x4,
-        camera_view_proj_matrix: Float4x4,
-        light_view_proj_matrix: Float4x4,
-    ) -> Self {
-        Self {
-            model_world_matrix,
-            camera_view_proj_matrix,
-            light_view_proj_matrix,
-        }
-    }
+    /// There must be one entry for every [vertex index](Model::vertex_indices) of the
+    /// model. Ignored if the input is [depth only](RenderPassShaderInput::depth_only).
+    pub attributes: Vec<T>,
 }
 
-impl<'a, 'b> VertexShader<RenderPassShaderInput<'a, 'b>, RenderPassShaderOutput>
+impl<'a> VertexShader<RenderPassShaderInput<'a>, RenderPassShaderOutput<VertexAttributes>>
     for RenderPassShader
 {
-    /// Apply vertex shader to vertices and normals in model space
-    fn transform(&self, input: &RenderPassShaderInput<'a, 'b>) -> RenderPassShaderOutput {
-        let world_vertices = input
+    /// Apply vertex shader to vertices, normals and tangents in model space
+    fn transform(
+        &self,
+        input: &RenderPassShaderInput<'a>,
+    ) -> RenderPassShaderOutput<VertexAttributes> {
+        let model = input.model;
+        let view_proj_matrix = input.view_proj_matrix();
+
+        let world_vertices = model
             .vertices
             .iter()
-            .map(|v| &self.model_world_matrix * Float4::from_point(*v))
+            .map(|v| &input.model_world_matrix * Float4::from_point(*v))
             .collect::<Vec<_>>();
 
         let vertices = world_vertices
             .iter()
-            .map(|v| &self.camera_view_proj_matrix * v)
-            .collect::<Vec<_>>();
-
-        let culling_bitmasks = vertices
-            .iter()
-            .map(|v| culling_bitmask(&v))
+            .map(|v| &view_proj_matrix * v)
             .collect::<Vec<_>>();
 
-        let light_vertices = world_vertices
-            .iter()
-            .map(|v| &self.light_view_proj_matrix * v)
-            .collect::<Vec<_>>();
+        if input.depth_only {
+            return RenderPassShaderOutput {
+                vertices,
+                attributes: Vec::new(),
+            };
+        }
 
         let vertices_attr = world_vertices
             .iter()
             .map(|v| v.xyz() / v.w)
             .collect::<Vec<_>>();
 
-        let normals = input
+        let normals = model
             .normals
             .iter()
             .map(|n| {
-                (&self.model_world_matrix * Float4::from_vector(*n))
+                (&input.model_world_matrix * Float4::from_vector(*n))
                     .xyz()
                     .normalized()
             })
             .collect::<Vec<_>>();
 
+        let tangents = model
+            .tangents
+            .iter()
+            .map(|t| {
+                let tangent = (input.model_world_matrix * Float4::from_vector(t.xyz()))
+                    .xyz()
+                    .normalized();
+                Float4::new(tangent.x, tangent.y, tangent.z, t.w)
+            })
+            .collect::<Vec<_>>();
+
+        let attributes = model
+            .vertex_indices
+            .iter()
+            .zip(model.texture_coord_indices.iter())
+            .zip(model.normal_indices.iter())
+            .zip(model.tangent_indices.iter())
+            .map(|(((&v, &uv), &n), &t)| {
+                VertexAttributes::new(
+                    vertices_attr[v],
+                    model.texture_coords[uv],
+                    normals[n],
+                    tangents[t],
+                    input.camera_position - vertices_attr[v],
+                )
+            })
+            .collect::<Vec<_>>();
+
         RenderPassShaderOutput {
             vertices,
-            culling_bitmasks,
-            light_vertices,
-            vertices_attr,
-            normals,
+            attributes,
+        }
+    }
+}
+
+/// Shaders applied to a model during render pass
+///
+/// The attributes output by the vertex shader are interpolated and handed to the pixel
+/// shader, so both have to agree on the attribute type. Every [PixelShader] of
+/// [VertexAttributes] is a model shader using the [RenderPassShader]. Shaders with
+/// custom attributes are combined in a [ShaderProgram].
+pub trait ModelShader: Send + Sync {
+    /// Run the vertex shader on a model and queue its triangles for shading with the
+    /// pixel shader
+    fn draw<'a>(&'a self, input: &RenderPassShaderInput<'a>, pass: &mut RenderPass<'a>);
+
+    /// Run the vertex shader on a model for a [depth only](RenderPassShaderInput::depth_only)
+    /// input and return the transformed homogeneous vertices
+    fn vertices(&self, input: &RenderPassShaderInput) -> Vec<Float4>;
+}
+
+/// A vertex shader and a pixel shader sharing the attribute type `T`
+pub struct ShaderProgram<V, P, T> {
+    /// Vertex shader computing the attributes
+    pub vertex_shader: V,
+    /// Pixel shader consuming the interpolated attributes
+    pub pixel_shader: P,
+    attributes: PhantomData<fn() -> T>,
+}
+
+impl<V, P, T> ShaderProgram<V, P, T> {
+    /// Create a new shader program
+    pub fn new(vertex_shader: V, pixel_shader: P) -> Self {
+        Self {
+            vertex_shader,
+            pixel_shader,
+            attributes: PhantomData,
         }
     }
 }
 
+impl<V, P, T> ModelShader for ShaderProgram<V, P, T>
+where
+    V: for<'b> VertexShader<RenderPassShaderInput<'b>, RenderPassShaderOutput<T>> + Send + Sync,
+    P: PixelShader<T>,
+    T: Varyings,
+{
+    fn draw<'a>(&'a self, input: &RenderPassShaderInput<'a>, pass: &mut RenderPass<'a>) {
+        let output = self.vertex_shader.transform(input);
+        pass.draw(input.model, output, &self.pixel_shader);
+    }
+
+    fn vertices(&self, input: &RenderPassShaderInput) -> Vec<Float4> {
+        self.vertex_shader.transform(input).vertices
+    }
+}
+
+impl<P> ModelShader for P
+where
+    P: PixelShader<VertexAttributes>,
+{
+    fn draw<'a>(&'a self, input: &RenderPassShaderInput<'a>, pass: &mut RenderPass<'a>) {
+        let output = RenderPassShader.transform(input);
+        pass.draw(input.model, output, self);
+    }
+
+    fn vertices(&self, input: &RenderPassShaderInput) -> Vec<Float4> {
+        RenderPassShader.transform(input).vertices
+    }
+}
+
+/// Per-frame state of the scene shared by all pixel shaders
+///
+/// The context is created by the renderer for every frame, so lights added to or
+/// removed from the scene are picked up without rebuilding any shaders.
+pub struct ShadingContext<'a> {
+    /// Lights illuminating the scene
+    pub lights: &'a Lights,
+    /// Camera the scene is rendered from
+    pub camera: &'a Camera,
+    /// Intensity of ambient light, used if there is no environment light
+    pub ambient_factor: f32,
+}
+
+impl<'a> ShadingContext<'a> {
+    /// Create a new shading context
+    pub fn new(lights: &'a Lights, camera: &'a Camera, ambient_factor: f32) -> Self {
+        Self {
+            lights,
+            camera,
+            ambient_factor,
+        }
+    }
+}
+
+/// Input to a pixel shader describing a single fragment
+#[derive(Debug, Clone, Copy)]
+pub struct PixelShaderInput<T = VertexAttributes> {
+    /// Vertex attributes interpolated with perspective correction
+    pub attrs: T,
+    /// Horizontal pixel coordinate
+    pub x: usize,
+    /// Vertical pixel coordinate, where 0 is the top row
+    pub y: usize,
+    /// Depth of the fragment in [0, 1] at the position the attributes are interpolated at
+    pub depth: f32,
+    /// Whether the front face of the triangle is visible
+    pub front_facing: bool,
+    /// Index of the triangle within its model
+    pub primitive_id: usize,
+    /// Change of the texture coordinates to the next pixel in x-direction
+    ///
+    /// Zero if the varyings carry no [texture coordinates](Varyings::uv).
+    pub duv_dx: Float2,
+    /// Change of the texture coordinates to the next pixel in y-direction
+    ///
+    /// Zero if the varyings carry no [texture coordinates](Varyings::uv).
+    pub duv_dy: Float2,
+}
+
+impl<T: Varyings> PixelShaderInput<T> {
+    /// Texture coordinates of the fragment with their screen-space derivatives, if the
+    /// varyings carry [texture coordinates](Varyings::uv)
+    pub fn texture_coordinates(&self) -> Option<TextureCoordinates> {
+        let uv = self.attrs.uv()?;
+        Some(TextureCoordinates::new(uv, self.duv_dx, self.duv_dy))
+    }
+}
+
+/// Output of a pixel shader
+#[derive(Debug, Clone, Copy)]
+pub enum PixelShaderOutput {
+    /// Color of the fragment and its opacity in [0, 1]
+    ///
+    /// The color is blended over the color already stored in the render target. Depth
+    /// is written regardless of the opacity, so translucent models should be rendered
+    /// after the opaque models behind them.
+    Color(Float3, f32),
+    /// The fragment is rejected and neither color nor depth are written
+    ///
+    /// Pixel shaders are not run when rendering shadow maps. There, fragments are only
+    /// discarded by the material's [alpha test](Material::alpha_cutoff), so fragments
+    /// a shader discards for other reasons still cast shadows.
+    Discard,
+}
+
 /// Trait describing a pixel shader
-pub trait PixelShader {
-    /// Given vertex attributes a pixel shader generates a color
-    fn color(&self, attrs: VertexAttributes) -> Float3;
+///
+/// A pixel shader implements a lighting model. The surface parameters are taken from the
+/// model's [material](Material) and the lights from the [shading context](ShadingContext).
+///
+/// Pixel shaders are invoked concurrently from the rasterizer's worker threads
+/// and therefore need to be shareable across threads.
+pub trait PixelShader<T: Varyings = VertexAttributes>: Send + Sync {
+    /// Given the interpolated vertex attributes of a fragment a pixel shader generates
+    /// a color
+    ///
+    /// [front_facing](PixelShaderInput::front_facing) states whether the front face of
+    /// the triangle is visible, which allows double-sided models to shade their back
+    /// faces differently. Textures should be sampled with the
+    /// [texture coordinates](PixelShaderInput::texture_coordinates) of the input, whose
+    /// derivatives select the mipmap level.
+    fn color(
+        &self,
+        context: &ShadingContext,
+        material: &Material,
+        input: &PixelShaderInput<T>,
+    ) -> Float3;
+
+    /// Shade a fragment, which is what the rasterizer invokes for every fragment
+    ///
+    /// By default, the fragment is discarded if the material's opacity is below its
+    /// [alpha cutoff](Material::alpha_cutoff). The alpha texture is only sampled if the
+    /// varyings carry [texture coordinates](Varyings::uv). Otherwise, the [color](PixelShader::color)
+    /// is returned with the material's opacity. Shaders which need screen-space
+    /// information or want to reject fragments on their own override this method.
+    fn shade(
+        &self,
+        context: &ShadingContext,
+        material: &Material,
+        input: &PixelShaderInput<T>,
+    ) -> PixelShaderOutput {
+        let alpha = input
+            .texture_coordinates()
+            .map_or(material.alpha, |uv| material.alpha(uv));
+        if material.alpha_cutoff.is_some_and(|cutoff| alpha < cutoff) {
+            return PixelShaderOutput::Discard;
+        }
+
+        let color = self.color(context, material, input);
+        PixelShaderOutput::Color(color, alpha)
+    }
+}
+
+/// Texture coordinates of a fragment with their screen-space derivatives
+///
+/// Unlike [PixelShaderInput::texture_coordinates] this does not return an option, since
+/// [VertexAttributes] always carry texture coordinates.
+fn vertex_texture_coordinates(input: &PixelShaderInput) -> TextureCoordinates {
+    TextureCoordinates::new(input.attrs.uv, input.duv_dx, input.duv_dy)
+}
+
+/// Normalized normal of the visible face
+///
+/// Normals of back faces are flipped such that double-sided models are lit from
+/// both sides.
+fn face_normal(normal: Float3, front_facing: bool) -> Float3 {
+    if front_facing {
+        normal.normalized()
+    } else {
+        -normal.normalized()
+    }
+}
+
+/// Normal of the visible face perturbed by the material's normal map, if any
+///
+/// The interpolated tangent is re-orthogonalized against the interpolated normal before
+/// the bitangent is reconstructed from its sign.
+fn mapped_normal(input: &PixelShaderInput, material: &Material) -> Float3 {
+    let attrs = &input.attrs;
+    let Some(texel) = material.tangent_space_normal(vertex_texture_coordinates(input)) else {
+        return face_normal(attrs.normal, input.front_facing);
+    };
+
+    let normal = attrs.normal.normalized();
+    let tangent = attrs.tangent.xyz();
+    let tangent = (tangent - normal * normal.dot(tangent)).normalized();
+    let bitangent = normal.cross(tangent) * attrs.tangent.w.signum();
+
+    let perturbed = (tangent * texel.x + bitangent * texel.y + normal * texel.z).normalized();
+
+    face_normal(perturbed, input.front_facing)
 }
 
-/// Pixel shader presenting a texture
-pub struct TextureShader {
-    /// The RGB texture to be sampled in the shader
-    pub texture: Texture<Float3>,
+/// Diffuse light arriving at a surface with the given normal from the environment
+///
+/// Without an [environment light](crate::light::EnvironmentLight), the ambient light is
+/// the constant [ambient factor](ShadingContext::ambient_factor) in all directions.
+fn ambient_light(context: &ShadingContext, normal: Float3) -> Float3 {
+    match &context.lights.environment {
+        Some(environment) => environment.diffuse(normal),
+        None => Float3::ones() * context.ambient_factor,
+    }
 }
 
-impl TextureShader {
-    /// Create a new texture shader
-    pub fn new(texture: Texture<Float3>) -> Self {
-        TextureShader { texture }
+/// Pixel shader presenting the base color of the material without any lighting
+pub struct UnlitShader;
+
+impl PixelShader for UnlitShader {
+    fn color(
+        &self,
+        _context: &ShadingContext,
+        material: &Material,
+        input: &PixelShaderInput,
+    ) -> Float3 {
+        material.base_color(vertex_texture_coordinates(input))
     }
 }
 
+/// Pixel shader presenting the base color texture of the material
+///
+/// The texture is sampled with the material's [sampler](Material::sampler) and, unlike
+/// with [UnlitShader], not tinted by the base color. Materials without a texture show
+/// their base color.
+pub struct TextureShader;
+
 impl PixelShader for TextureShader {
-    fn color(&self, attrs: VertexAttributes) -> Float3 {
-        self.texture.sample(attrs.uv)
+    fn color(
+        &self,
+        context: &ShadingContext,
+        material: &Material,
+        input: &PixelShaderInput,
+    ) -> Float3 {
+        match &material.base_color_texture {
+            Some(texture) => {
+                let uv = vertex_texture_coordinates(input);
+                let sampler = material.sampler.with_alpha();
+                texture
+                    .sample_grad(&sampler, uv.uv, uv.duv_dx, uv.duv_dy)
+                    .xyz()
+            }
+            None => UnlitShader.color(context, material, input),
+        }
     }
 }
 
-/// A diffuse color shader
+/// A diffuse color shader lit by a fixed direction and constant ambient light
+///
+/// The lights of the scene are ignored. The object color is the base color of the
+/// material.
 pub struct DiffuseShader {
-    /// Object color
-    pub color: Float3,
     /// Direction to surrounding light
     pub direction_to_light: Float3,
     /// Intensity of ambient light
@@ -251,9 +471,8 @@ pub struct DiffuseShader {
 
 impl DiffuseShader {
     /// Create a new diffuse shader
-    pub fn new(color: Float3, direction_to_light: Float3, ambient_factor: f32) -> Self {
+    pub fn new(direction_to_light: Float3, ambient_factor: f32) -> Self {
         DiffuseShader {
-            color,
             direction_to_light: direction_to_light.normalized(),
             ambient_factor,
         }
@@ -261,125 +480,552 @@ impl DiffuseShader {
 }
 
 impl PixelShader for DiffuseShader {
-    fn color(&self, attrs: VertexAttributes) -> Float3 {
-        let normal = attrs.normal.normalized();
+    fn color(
+        &self,
+        _context: &ShadingContext,
+        material: &Material,
+        input: &PixelShaderInput,
+    ) -> Float3 {
+        let normal = face_normal(input.attrs.normal, input.front_facing);
         let light_intensity = normal.dot(self.direction_to_light).max(0.0);
-        // (normal + 1.0) * 0.5
-        self.color * (self.ambient_factor + light_intensity)
+
+        material.base_color(vertex_texture_coordinates(input))
+            * (self.ambient_factor + light_intensity)
     }
 }
 
-/// A diffuse color shader supporting one spotlight
-pub struct DiffuseShaderWithSpotlight {
-    /// Color of the object
-    pub color: Float3,
-    /// Direction to surrounding light
-    pub direction_to_light: Float3,
-    /// Intensity of ambient light
-    pub ambient_factor: f32,
-    /// Spotlight
-    pub spotlight: Rc<RefCell<SpotLight>>,
-}
-
-impl DiffuseShaderWithSpotlight {
-    /// Create a new shader
-    pub fn new(
-        color: Float3,
-        direction_to_light: Float3,
-        ambient_factor: f32,
-        spotlight: Rc<RefCell<SpotLight>>,
-    ) -> Self {
-        DiffuseShaderWithSpotlight {
-            color,
-            direction_to_light,
-            ambient_factor,
-            spotlight,
+/// A diffuse (Lambertian) shader supporting any number of spotlights, directional
+/// lights, and point lights
+pub struct DiffuseShaderWithSpotlight;
+
+/// Poisson-disk distributed taps within the unit circle
+const POISSON_DISK: [(f32, f32); 16] = [
+    (-0.94201624, -0.39906216),
+    (0.9455861, -0.76890725),
+    (-0.0941841, -0.9293887),
+    (0.34495938, 0.2938776),
+    (-0.9158858, 0.45771432),
+    (-0.8154423, -0.87912464),
+    (-0.38277543, 0.27676845),
+    (0.974844, 0.7564838),
+    (0.44323325, -0.97511554),
+    (0.5374298, -0.4737342),
+    (-0.2649691, -0.41893023),
+    (0.79197514, 0.19090188),
+    (-0.2418884, 0.99706507),
+    (-0.81409955, 0.9143759),
+    (0.19984126, 0.78641367),
+    (0.14383161, -0.1410079),
+];
+
+/// Offset of the i-th of a number of taps of a kernel within [-1, 1]²
+fn kernel_tap(kernel: ShadowKernel, i: usize, taps: usize) -> Float2 {
+    match kernel {
+        ShadowKernel::PoissonDisk => Float2::new(POISSON_DISK[i].0, POISSON_DISK[i].1),
+        ShadowKernel::RotatedGrid => {
+            // Rotate by atan(1/2) such that no two taps share a row or column of texels
+            let (sin, cos) = (1.0 / 5f32.sqrt(), 2.0 / 5f32.sqrt());
+            let n = (taps as f32).sqrt().ceil() as usize;
+            let cell = |j: usize| {
+                if n > 1 {
+                    2.0 * j as f32 / (n - 1) as f32 - 1.0
+                } else {
+                    0.0
+                }
+            };
+            let (x, y) = (cell(i % n), cell(i / n));
+            // Scale down to stay within [-1, 1]² after the rotation
+            Float2::new(cos * x - sin * y, sin * x + cos * y) / (cos + sin)
         }
     }
 }
 
-fn linearize_depth(depth: f32, near: f32, far: f32) -> f32 {
-    2.0 * far * near / (far + near - (2.0 * depth - 1.0) * (far - near))
+/// Number of taps used by a kernel
+fn kernel_taps(kernel: ShadowKernel, taps: usize) -> usize {
+    match kernel {
+        ShadowKernel::PoissonDisk => taps.clamp(1, POISSON_DISK.len()),
+        ShadowKernel::RotatedGrid => {
+            // Complete the grid, a partial grid would shift the shadow
+            let n = (taps.max(1) as f32).sqrt().ceil() as usize;
+            n * n
+        }
+    }
 }
 
-impl PixelShader for DiffuseShaderWithSpotlight {
-    fn color(&self, attrs: VertexAttributes) -> Float3 {
-        let spotlight = self.spotlight.borrow();
+/// Depth along the negative z-axis in the light's view space stored in the texel of a
+/// shadow map at texture coordinates
+fn shadow_map_depth(camera: &Camera, shadow_map: &Texture<f32>, uv: Float2) -> f32 {
+    let x = (uv.x * shadow_map.width as f32).clamp(0.0, shadow_map.width as f32 - 1.0) as usize;
+    let y = ((1.0 - uv.y) * shadow_map.height as f32).clamp(0.0, shadow_map.height as f32 - 1.0)
+        as usize;
+    camera.linear_depth(shadow_map.image[y * shadow_map.width + x])
+}
 
-        let normal = attrs.normal.normalized();
-        let light_intensity = normal.dot(self.direction_to_light).max(0.0);
-        let to_light = spotlight.position - attrs.vertex;
-        let dir_to_light = to_light.normalized();
-        let dir_to_target = (spotlight.position - spotlight.target).normalized();
-        let light_fragment = attrs.light_vertex.xyz() / attrs.light_vertex.w * 0.5 + 0.5;
-        // Goal here: avoid shadow acne without getting peter panning
-        // TODO: Find better way to choose bias
-        let bias = (0.5 * (1.0 - normal.dot(dir_to_light))).max(0.1);
-        let bias = 0.5;
-
-        let percentage_in_light = if light_fragment.x >= 0.0
-            && light_fragment.x <= 1.0
-            && light_fragment.y >= 0.0
-            && light_fragment.y <= 1.0
-            && light_fragment.z >= 0.0
-            && light_fragment.z <= 1.0
-        {
-            // Percentage-closer filtering
-            let linear_frag_z = linearize_depth(
-                light_fragment.z,
-                spotlight.camera.near,
-                spotlight.camera.far,
-            );
-
-            let sampler = Uniform::new(0.0, 1.0).unwrap();
-            let mut rng = rand::rng();
-            let width = spotlight.shadow_map.width as f32 - 1.0;
-            let height = spotlight.shadow_map.height as f32 - 1.0;
-            // let xsign = [0.0];
-            // let ysign = [0.0];
-            // let xsign = [1.0, -1.0];
-            // let ysign = [1.0, -1.0];
-            // let xsign = [1.0, -0.0, -1.0];
-            // let ysign = [1.0, -0.0, -1.0];
-            let xsign = [1.5, -0.5, -1.5];
-            let ysign = [1.5, -0.5, -1.5];
-            let repeats = 1;
-            let mut p = 0.0;
-            for _ in 0..repeats {
-                for xs in xsign {
-                    for ys in ysign {
-                        let x = (light_fragment.x * width + xs * sampler.sample(&mut rng))
-                            .clamp(0.0, width) as usize;
-                        let y = ((1.0 - light_fragment.y) * height + ys * sampler.sample(&mut rng))
-                            .clamp(0.0, height) as usize;
-
-                        p += (linear_frag_z + bias
-                            >= linearize_depth(
-                                spotlight.shadow_map.image[y * spotlight.shadow_map.width + x],
-                                spotlight.camera.near,
-                                spotlight.camera.far,
-                            )) as i32 as f32;
-                    }
+/// Fraction of the kernel's taps around texture coordinates which are not occluded
+fn percentage_closer_filter(
+    camera: &Camera,
+    shadow_map: &Texture<f32>,
+    uv: Float2,
+    receiver: f32,
+    kernel: ShadowKernel,
+    taps: usize,
+    radius: Float2,
+) -> f32 {
+    let taps = kernel_taps(kernel, taps);
+    let lit = (0..taps)
+        .filter(|&i| {
+            let tap = uv + kernel_tap(kernel, i, taps) * radius;
+            receiver >= shadow_map_depth(camera, shadow_map, tap)
+        })
+        .count();
+
+    lit as f32 / taps as f32
+}
+
+/// Moments stored for a depth of a shadow map rendered with `camera`
+///
+/// Variance shadow maps store the normalized linear depth and its square, exponential
+/// shadow maps store the exponential of the normalized linear depth in the first
+/// component.
+pub(crate) fn shadow_map_moments(camera: &Camera, filter: &ShadowFilter, depth: f32) -> Float2 {
+    let depth = camera.normalized_linear_depth(depth);
+    match *filter {
+        ShadowFilter::Exponential { exponent, .. } => Float2::new((exponent * depth).exp(), 0.0),
+        _ => Float2::new(depth, depth * depth),
+    }
+}
+
+/// Bilinear lookup of the moments of a shadow map at texture coordinates
+fn sample_moments(shadow_moments: &Texture<Float2>, uv: Float2) -> Float2 {
+    sample_bilinear(
+        &shadow_moments.image,
+        shadow_moments.width,
+        shadow_moments.height,
+        uv.x * shadow_moments.width as f32,
+        (1.0 - uv.y) * shadow_moments.height as f32,
+    )
+}
+
+/// Fraction of a light's shadow map samples around a position which are not occluded
+/// (0 is fully in shadow, 1 is fully lit)
+///
+/// The shadow map has been rendered with `camera`, whose view-projection matrix is
+/// `shadow_matrix`. Positions outside of the shadow map are considered to be lit.
+fn percentage_in_light(
+    camera: &Camera,
+    shadow_matrix: &Float4x4,
+    shadow_map: &Texture<f32>,
+    shadow_moments: &Texture<Float2>,
+    filter: &ShadowFilter,
+    position: Float3,
+) -> f32 {
+    let light_vertex = shadow_matrix * Float4::from_point(position);
+    let light_fragment = light_vertex.xyz() / light_vertex.w * 0.5 + 0.5;
+
+    if light_fragment.x < 0.0
+        || light_fragment.x > 1.0
+        || light_fragment.y < 0.0
+        || light_fragment.y > 1.0
+        || light_fragment.z < 0.0
+        || light_fragment.z > 1.0
+    {
+        return 1.0;
+    }
+
+    let uv = light_fragment.xy();
+    let receiver = camera.linear_depth(light_fragment.z);
+    let texel = Float2::new(
+        1.0 / shadow_map.width as f32,
+        1.0 / shadow_map.height as f32,
+    );
+
+    match *filter {
+        ShadowFilter::Hard => (receiver >= shadow_map_depth(camera, shadow_map, uv)) as i32 as f32,
+        ShadowFilter::Pcf {
+            kernel,
+            taps,
+            radius,
+        } => percentage_closer_filter(
+            camera,
+            shadow_map,
+            uv,
+            receiver,
+            kernel,
+            taps,
+            texel * radius,
+        ),
+        ShadowFilter::Pcss {
+            kernel,
+            taps,
+            light_size,
+        } => {
+            // Size of the light relative to the width of the shadow map at the near plane
+            let frustum_size = Float2::new(camera.right - camera.left, camera.top - camera.bottom);
+            let light_size_uv = Float2::new(light_size, light_size) / frustum_size;
+
+            // Positive distances to the light
+            let near = -camera.near;
+            let receiver_distance = -receiver;
+            let perspective = camera.projection_type == ProjectionType::Perspective;
+
+            // Blocker search within the area of the shadow map from which the light is
+            // partially occluded
+            let search_radius = if perspective {
+                light_size_uv * ((receiver_distance - near) / receiver_distance)
+            } else {
+                light_size_uv * (receiver_distance - near)
+            };
+            let search_taps = kernel_taps(kernel, taps);
+            let (mut blocker_sum, mut blockers) = (0.0, 0);
+            for i in 0..search_taps {
+                let tap = uv + kernel_tap(kernel, i, search_taps) * search_radius;
+                let depth = shadow_map_depth(camera, shadow_map, tap);
+                if depth > receiver {
+                    blocker_sum += -depth;
+                    blockers += 1;
                 }
             }
 
-            p / (repeats as f32 * (xsign.len() * xsign.len()) as f32)
-        } else {
-            0.0
+            if blockers == 0 {
+                return 1.0;
+            }
+
+            // Penumbra size by similar triangles between light, blocker and receiver
+            let blocker_distance = blocker_sum / blockers as f32;
+            let filter_radius = if perspective {
+                light_size_uv
+                    * ((receiver_distance - blocker_distance) / blocker_distance)
+                    * (near / receiver_distance)
+            } else {
+                light_size_uv * (receiver_distance - blocker_distance)
+            };
+
+            percentage_closer_filter(
+                camera,
+                shadow_map,
+                uv,
+                receiver,
+                kernel,
+                taps,
+                Float2::new(filter_radius.x.max(texel.x), filter_radius.y.max(texel.y)),
+            )
+        }
+        ShadowFilter::Variance {
+            min_variance,
+            light_bleeding_reduction,
+            ..
+        } => {
+            let receiver = (receiver - camera.near) / (camera.far - camera.near);
+            let moments = sample_moments(shadow_moments, uv);
+            if receiver <= moments.x {
+                return 1.0;
+            }
+
+            // Upper bound of the fraction of unoccluded depths by Chebyshev's inequality
+            let variance = (moments.y - moments.x * moments.x).max(min_variance);
+            let d = receiver - moments.x;
+            let p_max = variance / (variance + d * d);
+
+            // Cut off the tail of the bound which causes light bleeding
+            ((p_max - light_bleeding_reduction) / (1.0 - light_bleeding_reduction)).clamp(0.0, 1.0)
+        }
+        ShadowFilter::Exponential { exponent, .. } => {
+            let receiver = (receiver - camera.near) / (camera.far - camera.near);
+            let moments = sample_moments(shadow_moments, uv);
+            (moments.x * (-exponent * receiver).exp()).clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// Call `f` with the direction to the light and the radiance arriving at a position for
+/// every light illuminating the side of the surface the normal points to
+///
+/// The radiance includes the light's color, its distance attenuation or cone, and the
+/// fraction which is not occluded according to its shadow map.
+fn for_each_light<F>(lights: &Lights, position: Float3, normal: Float3, mut f: F)
+where
+    F: FnMut(Float3, Float3),
+{
+    for light in lights.directional_lights.iter() {
+        let light = light.read().unwrap();
+
+        let dir_to_light = -light.direction;
+        if normal.dot(dir_to_light) <= 0.0 {
+            continue;
+        }
+
+        // Move the position along the normal to avoid shadow acne
+        let shadow_position = position + normal * light.shadow_bias.normal_offset;
+
+        // Pick the shadow map cascade by view depth and blend with the next one
+        let shadow = match light.select_cascade(position) {
+            Some((i, weight)) => {
+                let cascade = &light.cascades[i];
+                let mut shadow = percentage_in_light(
+                    &cascade.camera,
+                    &cascade.shadow_matrix,
+                    &cascade.shadow_map,
+                    &cascade.shadow_moments,
+                    &light.shadow_filter,
+                    shadow_position,
+                );
+                if weight > 0.0 {
+                    let next = &light.cascades[i + 1];
+                    let next_shadow = percentage_in_light(
+                        &next.camera,
+                        &next.shadow_matrix,
+                        &next.shadow_map,
+                        &next.shadow_moments,
+                        &light.shadow_filter,
+                        shadow_position,
+                    );
+                    shadow = shadow * (1.0 - weight) + next_shadow * weight;
+                }
+                shadow
+            }
+            None => 1.0,
         };
 
-        let spot_intensity = if dir_to_light.dot(dir_to_target) > spotlight.angle.cos() {
-            normal.dot(dir_to_light).max(0.0) / to_light.norm()
-        } else {
-            0.0
+        f(dir_to_light, light.color * shadow);
+    }
+
+    for spotlight in lights.spotlights.iter() {
+        let spotlight = spotlight.read().unwrap();
+
+        let to_light = spotlight.position - position;
+        let distance = to_light.norm();
+        let dir_to_light = to_light / distance;
+        let dir_to_target = (spotlight.position - spotlight.target).normalized();
+
+        if dir_to_light.dot(dir_to_target) <= spotlight.angle.cos()
+            || normal.dot(dir_to_light) <= 0.0
+        {
+            continue;
+        }
+
+        let shadow_position = position + normal * spotlight.shadow_bias.normal_offset;
+        let shadow = percentage_in_light(
+            &spotlight.camera,
+            &spotlight.shadow_matrix,
+            &spotlight.shadow_map,
+            &spotlight.shadow_moments,
+            &spotlight.shadow_filter,
+            shadow_position,
+        );
+
+        f(dir_to_light, spotlight.color * (shadow / distance));
+    }
+
+    for light in lights.point_lights.iter() {
+        let light = light.read().unwrap();
+
+        let to_light = light.position - position;
+        let distance = to_light.norm();
+        let dir_to_light = to_light / distance;
+        if normal.dot(dir_to_light) <= 0.0 {
+            continue;
+        }
+
+        // Look up the cube shadow map face in the direction of the fragment
+        let shadow_position = position + normal * light.shadow_bias.normal_offset;
+        let face = light.face(shadow_position - light.position);
+        let shadow = percentage_in_light(
+            &light.cameras[face],
+            &light.shadow_matrices[face],
+            &light.shadow_maps[face],
+            &light.shadow_moments[face],
+            &light.shadow_filter,
+            shadow_position,
+        );
+
+        f(
+            dir_to_light,
+            light.color * (light.attenuation(distance) * shadow),
+        );
+    }
+}
+
+impl PixelShader for DiffuseShaderWithSpotlight {
+    fn color(
+        &self,
+        context: &ShadingContext,
+        material: &Material,
+        input: &PixelShaderInput,
+    ) -> Float3 {
+        let attrs = &input.attrs;
+        let uv = vertex_texture_coordinates(input);
+        let normal = mapped_normal(input, material);
+        let diffuse = material.base_color(uv);
+
+        let mut color = diffuse * (ambient_light(context, normal) * material.ambient_occlusion(uv));
+        for_each_light(
+            context.lights,
+            attrs.vertex,
+            normal,
+            |dir_to_light, radiance| {
+                color += diffuse * radiance * normal.dot(dir_to_light);
+            },
+        );
+
+        // Gamma-correction
+        color.powf(1.0 / 2.2)
+    }
+}
+
+/// A Blinn-Phong shader supporting any number of spotlights, directional lights, and
+/// point lights
+///
+/// The specular highlight depends on the angle between the normal and the half-way vector
+/// between the directions to the light and to the eye.
+///
+/// The diffuse color is the base color of the material.
+pub struct BlinnPhongShader;
+
+impl PixelShader for BlinnPhongShader {
+    fn color(
+        &self,
+        context: &ShadingContext,
+        material: &Material,
+        input: &PixelShaderInput,
+    ) -> Float3 {
+        let attrs = &input.attrs;
+        let uv = vertex_texture_coordinates(input);
+        let normal = mapped_normal(input, material);
+        let dir_to_eye = attrs.to_eye.normalized();
+
+        let diffuse = material.base_color(uv);
+        let specular = material.specular_color(uv);
+
+        let mut color = diffuse * (ambient_light(context, normal) * material.ambient_occlusion(uv));
+        for_each_light(
+            context.lights,
+            attrs.vertex,
+            normal,
+            |dir_to_light, radiance| {
+                let half_way = (dir_to_light + dir_to_eye).normalized();
+                let specular_intensity = normal.dot(half_way).max(0.0).powf(material.shininess);
+
+                color +=
+                    (diffuse * normal.dot(dir_to_light) + specular * specular_intensity) * radiance;
+            },
+        );
+
+        // Gamma-correction
+        color.powf(1.0 / 2.2)
+    }
+}
+
+/// A physically based shader following the metallic-roughness workflow supporting any
+/// number of spotlights, directional lights, and point lights
+///
+/// The specular term is the Cook-Torrance microfacet model with the GGX normal
+/// distribution, the Smith geometry term (with Schlick's approximation of GGX) and
+/// Schlick's approximation of Fresnel reflectance. The diffuse term is Lambertian.
+/// Like in the other shaders, a white diffuse surface facing a light reflects the
+/// light's color, so the BRDF is scaled by π for the lights of the scene.
+/// An [environment light](crate::light::EnvironmentLight) contributes diffuse light and
+/// prefiltered reflections.
+pub struct PbrShader;
+
+/// GGX (Trowbridge-Reitz) normal distribution function
+pub(crate) fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (std::f32::consts::PI * d * d)
+}
+
+/// Smith geometry term with Schlick's approximation of GGX for direct lighting
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let schlick_ggx = |n_dot_x: f32| n_dot_x / (n_dot_x * (1.0 - k) + k);
+    schlick_ggx(n_dot_v) * schlick_ggx(n_dot_l)
+}
+
+/// Specular reflectance integrated over the hemisphere, which scales the prefiltered
+/// environment in the split-sum approximation
+///
+/// Uses the analytical fit of Karis, "Physically Based Shading on Mobile", instead of a
+/// lookup table.
+fn environment_brdf(f0: Float3, n_dot_v: f32, roughness: f32) -> Float3 {
+    let r = Float4::new(-1.0, -0.0275, -0.572, 0.022) * roughness
+        + Float4::new(1.0, 0.0425, 1.04, -0.04);
+    let a004 = (r.x * r.x).min((-9.28 * n_dot_v).exp2()) * r.x + r.y;
+    let scale = -1.04 * a004 + r.z;
+    let bias = 1.04 * a004 + r.w;
+
+    f0 * scale + bias
+}
+
+/// Schlick's approximation of the Fresnel reflectance
+fn fresnel_schlick(cos_theta: f32, f0: Float3) -> Float3 {
+    f0 + (Float3::ones() - f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+impl PixelShader for PbrShader {
+    fn color(
+        &self,
+        context: &ShadingContext,
+        material: &Material,
+        input: &PixelShaderInput,
+    ) -> Float3 {
+        let attrs = &input.attrs;
+        let uv = vertex_texture_coordinates(input);
+        let normal = mapped_normal(input, material);
+        let dir_to_eye = attrs.to_eye.normalized();
+
+        let base_color = material.base_color(uv);
+        let (metallic, roughness) = material.metallic_roughness(uv);
+        // Avoid a singular normal distribution for perfectly smooth surfaces
+        let roughness = roughness.clamp(0.04, 1.0);
+
+        // Reflectance at normal incidence, dielectrics reflect about 4%
+        let f0 = Float3::new(0.04, 0.04, 0.04).lerp(base_color, metallic);
+        let n_dot_v = normal.dot(dir_to_eye).max(1e-4);
+
+        let ambient_occlusion = material.ambient_occlusion(uv);
+        let mut color = match &context.lights.environment {
+            Some(environment) => {
+                // Split-sum approximation of the reflected environment, the rest of the
+                // light is diffusely reflected by dielectrics
+                let reflectance = environment_brdf(f0, n_dot_v, roughness);
+                let reflected = normal * (2.0 * normal.dot(dir_to_eye)) - dir_to_eye;
+                let specular = environment.specular(reflected, roughness) * reflectance;
+                let diffuse = (Float3::ones() - reflectance)
+                    * (1.0 - metallic)
+                    * base_color
+                    * environment.diffuse(normal);
+
+                (diffuse + specular) * ambient_occlusion
+            }
+            None => base_color * (context.ambient_factor * ambient_occlusion),
         };
+        for_each_light(
+            context.lights,
+            attrs.vertex,
+            normal,
+            |dir_to_light, radiance| {
+                let half_way = (dir_to_light + dir_to_eye).normalized();
+                let n_dot_l = normal.dot(dir_to_light);
+                let n_dot_h = normal.dot(half_way).max(0.0);
+
+                let fresnel = fresnel_schlick(half_way.dot(dir_to_eye).max(0.0), f0);
+                let specular = fresnel
+                    * (distribution_ggx(n_dot_h, roughness)
+                        * geometry_smith(n_dot_v, n_dot_l, roughness)
+                        / (4.0 * n_dot_v * n_dot_l).max(1e-4));
+
+                // Metals have no diffuse reflection, the rest of the light is refracted
+                let diffuse = (Float3::ones() - fresnel) * (1.0 - metallic) * base_color
+                    / std::f32::consts::PI;
 
-        Float3::new(percentage_in_light, percentage_in_light, percentage_in_light)
+                // Light colors are the radiance reflected by a white Lambertian surface
+                // facing the light, which is the irradiance divided by π
+                color += (diffuse + specular) * radiance * (n_dot_l * std::f32::consts::PI);
+            },
+        );
 
-        // let color = self.color * 0.1
-        //     + self.color * light_intensity * 0.2
-        //     + self.color * spotlight.color * spot_intensity * percentage_in_light * 3.0;
-        // // Gamma-correction
-        // color.powf(1.0 / 2.2)
+        // Gamma-correction
+        color.powf(1.0 / 2.2)
     }
 }
@@ -1,6 +1,10 @@
-use crate::math::{Float2, Float3};
-use png::{ColorType, Decoder};
+use crate::math::{Float2, Float3, Float4};
+use png::{BitDepth, ColorType, Decoder, DecodingError, Transformations};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::fs::File;
+use std::io::BufReader;
+use std::ops::{Add, Mul};
 
 /// A texture of generic type `T`
 #[derive(Debug, Clone)]
@@ -14,6 +18,143 @@ where
     pub height: usize,
     /// Image data
     pub image: Vec<T>,
+    /// Downsampled levels of detail, each half the size of the previous one
+    ///
+    /// Empty unless [generate_mipmaps](Texture::generate_mipmaps) was called. The
+    /// texture itself is level 0, so `mipmaps[0]` is level 1.
+    pub mipmaps: Vec<Texture<T>>,
+}
+
+/// Error returned when a texture cannot be loaded
+#[derive(Debug)]
+pub enum TextureError {
+    /// The file could not be opened
+    Io {
+        /// Path of the file
+        path: String,
+        /// Underlying I/O error
+        source: std::io::Error,
+    },
+    /// The file is not a valid PNG image
+    Decoding {
+        /// Path of the file
+        path: String,
+        /// Underlying decoding error
+        source: DecodingError,
+    },
+    /// The decoded image has a pixel format which cannot be converted to colors
+    UnsupportedFormat {
+        /// Path of the file
+        path: String,
+        /// Color type of the decoded image
+        color_type: ColorType,
+        /// Bit depth of the decoded image
+        bit_depth: BitDepth,
+    },
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TextureError::Io { path, source } => write!(f, "failed to open {path}: {source}"),
+            TextureError::Decoding { path, source } => {
+                write!(f, "failed to decode PNG {path}: {source}")
+            }
+            TextureError::UnsupportedFormat {
+                path,
+                color_type,
+                bit_depth,
+            } => write!(
+                f,
+                "unsupported pixel format {color_type:?} with {} bits per sample in {path}",
+                *bit_depth as u8
+            ),
+        }
+    }
+}
+
+impl Error for TextureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TextureError::Io { source, .. } => Some(source),
+            TextureError::Decoding { source, .. } => Some(source),
+            TextureError::UnsupportedFormat { .. } => None,
+        }
+    }
+}
+
+/// Decode a PNG file of any color type and bit depth to RGBA colors in [0.0, 1.0]
+///
+/// Palettes, grayscale images with less than 8 bits, and transparency given by a tRNS
+/// chunk are expanded by the decoder. Grayscale is replicated to all color channels
+/// and images without alpha channel are opaque.
+fn read_png(path: &str) -> Result<Texture<Float4>, TextureError> {
+    let file = File::open(path).map_err(|source| TextureError::Io {
+        path: path.to_string(),
+        source,
+    })?;
+    let decoding_error = |source| TextureError::Decoding {
+        path: path.to_string(),
+        source,
+    };
+
+    let mut decoder = Decoder::new(BufReader::new(file));
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(decoding_error)?;
+    // Allocate and read to buffer
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(decoding_error)?;
+    // Grab bytes of image
+    let bytes = &buf[..info.buffer_size()];
+
+    // Normalize samples of 8 or 16 bits (big endian) to [0.0, 1.0]
+    let samples = match info.bit_depth {
+        BitDepth::Eight => bytes.iter().map(|&b| b as f32 / 255.0).collect::<Vec<_>>(),
+        BitDepth::Sixteen => bytes
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as f32 / 65535.0)
+            .collect::<Vec<_>>(),
+        _ => {
+            return Err(TextureError::UnsupportedFormat {
+                path: path.to_string(),
+                color_type: info.color_type,
+                bit_depth: info.bit_depth,
+            });
+        }
+    };
+
+    let image = match info.color_type {
+        ColorType::Grayscale => samples
+            .iter()
+            .map(|&v| Float4::new(v, v, v, 1.0))
+            .collect::<Vec<_>>(),
+        ColorType::GrayscaleAlpha => samples
+            .chunks_exact(2)
+            .map(|s| Float4::new(s[0], s[0], s[0], s[1]))
+            .collect::<Vec<_>>(),
+        ColorType::Rgb => samples
+            .chunks_exact(3)
+            .map(|s| Float4::new(s[0], s[1], s[2], 1.0))
+            .collect::<Vec<_>>(),
+        ColorType::Rgba => samples
+            .chunks_exact(4)
+            .map(|s| Float4::new(s[0], s[1], s[2], s[3]))
+            .collect::<Vec<_>>(),
+        ColorType::Indexed => {
+            return Err(TextureError::UnsupportedFormat {
+                path: path.to_string(),
+                color_type: info.color_type,
+                bit_depth: info.bit_depth,
+            });
+        }
+    };
+
+    Ok(Texture {
+        width: info.width as usize,
+        height: info.height as usize,
+        image,
+        mipmaps: Vec::new(),
+    })
 }
 
 impl Texture<Float3> {
@@ -21,42 +162,27 @@ impl Texture<Float3> {
     ///
     /// Colors are represented as [Float3's](crate::math::Float3) with each
     /// component representing red, green, or blue in the interval [0.0, 1.0].
-    pub fn from_png(path: &str) -> Texture<Float3> {
-        let decoder = Decoder::new(File::open(path).unwrap());
-        let mut reader = decoder.read_info().unwrap();
-        // Allocate and read to buffer
-        let mut buf = vec![0; reader.output_buffer_size()];
-        let info = reader.next_frame(&mut buf).unwrap();
-        // Grab bytes of image
-        let bytes = buf[..info.buffer_size()].to_owned();
-
-        let image = match info.color_type {
-            ColorType::Indexed => {
-                if let Some(palette) = &reader.info().palette {
-                    bytes
-                        .into_iter()
-                        .map(|b| {
-                            Float3::new(
-                                palette[3 * b as usize] as f32 / 255.0,
-                                palette[3 * b as usize + 1] as f32 / 255.0,
-                                palette[3 * b as usize + 2] as f32 / 255.0,
-                            )
-                        })
-                        .collect::<Vec<_>>()
-                } else {
-                    panic!("Color type is `Indexed`, but missing palette in {path}")
-                }
-            }
-            _ => {
-                panic!("Color type {:?} not supported", info.color_type)
-            }
-        };
+    /// The alpha channel is dropped, load a `Texture<Float4>` to keep it.
+    pub fn from_png(path: &str) -> Result<Texture<Float3>, TextureError> {
+        let texture = read_png(path)?;
 
-        Texture {
-            width: info.width as usize,
-            height: info.height as usize,
-            image,
-        }
+        Ok(Texture {
+            width: texture.width,
+            height: texture.height,
+            image: texture.image.iter().map(|c| c.xyz()).collect(),
+            mipmaps: Vec::new(),
+        })
+    }
+}
+
+impl Texture<Float4> {
+    /// Load color-texture with alpha channel from PNG file.
+    ///
+    /// Colors are represented as [Float4's](crate::math::Float4) with each
+    /// component representing red, green, blue, or alpha in the interval [0.0, 1.0].
+    /// Images without alpha channel or transparency are opaque (alpha of 1.0).
+    pub fn from_png(path: &str) -> Result<Texture<Float4>, TextureError> {
+        read_png(path)
     }
 }
 
@@ -67,12 +193,13 @@ where
     /// Create a new texture
     pub fn new(width: usize, height: usize) -> Texture<T> {
         let mut image: Vec<T> = Vec::new();
-        image.resize(width * height * size_of::<T>(), T::default());
+        image.resize(width * height, T::default());
 
         Texture {
             width,
             height,
             image,
+            mipmaps: Vec::new(),
         }
     }
 
@@ -86,3 +213,772 @@ where
         self.image[y * self.width + x]
     }
 }
+
+impl<T> Texture<T>
+where
+    T: Copy + Default + Add<Output = T> + Mul<f32, Output = T>,
+{
+    /// Sample from the texture at coordinates (u, v) with the given sampler
+    ///
+    /// Texel centers lie at half-integer multiples of the texel size. Like in
+    /// [sample](Texture::sample), v = 0 corresponds to the bottom row of the image.
+    pub fn sample_with(&self, sampler: &Sampler<T>, texture_coord: Float2) -> T {
+        let x = texture_coord.x * self.width as f32;
+        let y = (1.0 - texture_coord.y) * self.height as f32;
+
+        match sampler.filter {
+            FilterMode::Nearest => self.texel(sampler, x.floor() as i64, y.floor() as i64),
+            FilterMode::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top =
+                    self.texel(sampler, x0, y0) * (1.0 - fx) + self.texel(sampler, x0 + 1, y0) * fx;
+                let bottom = self.texel(sampler, x0, y0 + 1) * (1.0 - fx)
+                    + self.texel(sampler, x0 + 1, y0 + 1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
+    }
+
+    /// Sample from the texture using the screen-space derivatives of the coordinates
+    ///
+    /// The derivatives span the footprint of the pixel in the texture, which selects
+    /// the mipmap level according to the sampler's [mipmap mode](Sampler::mipmap_mode).
+    /// With [anisotropic filtering](Sampler::max_anisotropy), several samples along
+    /// the longer axis of the footprint are taken from a more detailed level. Without
+    /// mipmaps this is the same as [sample_with](Texture::sample_with).
+    pub fn sample_grad(
+        &self,
+        sampler: &Sampler<T>,
+        texture_coord: Float2,
+        duv_dx: Float2,
+        duv_dy: Float2,
+    ) -> T {
+        if self.mipmaps.is_empty() || sampler.mipmap_mode == MipmapMode::None {
+            return self.sample_with(sampler, texture_coord);
+        }
+
+        // Axes of the footprint in texels of level 0
+        let size = Float2::new(self.width as f32, self.height as f32);
+        let (dx, dy) = (duv_dx * size, duv_dy * size);
+        let (length_x, length_y) = (dx.dot(dx).sqrt(), dy.dot(dy).sqrt());
+        let (major_axis, major, minor) = if length_x >= length_y {
+            (duv_dx, length_x, length_y)
+        } else {
+            (duv_dy, length_y, length_x)
+        };
+
+        // Number of samples, which is at least one even for degenerate footprints
+        let count = ((major / minor.max(f32::MIN_POSITIVE)).ceil() as usize)
+            .clamp(1, sampler.max_anisotropy.max(1));
+        let lod = (major / count as f32).log2();
+        if count == 1 {
+            return self.sample_level(sampler, texture_coord, lod);
+        }
+
+        // Spread the samples evenly over the major axis, centered on the pixel
+        let step = major_axis / count as f32;
+        let first = texture_coord - major_axis * 0.5 + step * 0.5;
+        (0..count).fold(T::default(), |sum, i| {
+            sum + self.sample_level(sampler, first + step * i as f32, lod) * (1.0 / count as f32)
+        })
+    }
+
+    /// Sample at a fractional level of detail, 0 being the texture itself
+    fn sample_level(&self, sampler: &Sampler<T>, texture_coord: Float2, lod: f32) -> T {
+        let lod = lod.clamp(0.0, self.mipmaps.len() as f32);
+
+        match sampler.mipmap_mode {
+            MipmapMode::None => self.sample_with(sampler, texture_coord),
+            MipmapMode::Nearest => self
+                .level(lod.round() as usize)
+                .sample_with(sampler, texture_coord),
+            MipmapMode::Linear => {
+                let level = lod.floor() as usize;
+                let t = lod.fract();
+                let detailed = self.level(level).sample_with(sampler, texture_coord);
+                if t > 0.0 {
+                    let coarse = self.level(level + 1).sample_with(sampler, texture_coord);
+                    detailed * (1.0 - t) + coarse * t
+                } else {
+                    detailed
+                }
+            }
+        }
+    }
+
+    /// Mipmap level, 0 being the texture itself
+    fn level(&self, level: usize) -> &Texture<T> {
+        match level {
+            0 => self,
+            _ => &self.mipmaps[level - 1],
+        }
+    }
+
+    /// Generate the chain of mipmaps down to a single texel
+    ///
+    /// Every level is half the size of the previous one, rounded down to at least one
+    /// texel. For odd sizes a texel of the smaller level covers slightly more than two
+    /// texels of the previous level, and the filter weights follow this footprint
+    /// exactly. Texels beyond the edges are clamped.
+    pub fn generate_mipmaps(&mut self, filter: MipmapFilter) {
+        self.mipmaps.clear();
+
+        loop {
+            let level = self.mipmaps.last().unwrap_or(self);
+            if level.width * level.height <= 1 {
+                break;
+            }
+            let next = level.downsample(filter);
+            self.mipmaps.push(next);
+        }
+    }
+
+    /// Texture with half the size filtered separately along rows and columns
+    fn downsample(&self, filter: MipmapFilter) -> Texture<T> {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let filtered = |taps: &[(usize, f32)], texel: &dyn Fn(usize) -> T| {
+            taps.iter()
+                .fold(T::default(), |sum, &(i, weight)| sum + texel(i) * weight)
+        };
+
+        let mut rows = Vec::with_capacity(width * self.height);
+        let weights = filter.weights(self.width, width);
+        for y in 0..self.height {
+            for taps in &weights {
+                rows.push(filtered(taps, &|x| self.image[y * self.width + x]));
+            }
+        }
+
+        let mut image = Vec::with_capacity(width * height);
+        for taps in &filter.weights(self.height, height) {
+            for x in 0..width {
+                image.push(filtered(taps, &|y| rows[y * width + x]));
+            }
+        }
+
+        Texture {
+            width,
+            height,
+            image,
+            mipmaps: Vec::new(),
+        }
+    }
+
+    /// Texel at integer coordinates, which are wrapped according to the sampler
+    fn texel(&self, sampler: &Sampler<T>, x: i64, y: i64) -> T {
+        match (
+            sampler.wrap_u.wrap(x, self.width),
+            sampler.wrap_v.wrap(y, self.height),
+        ) {
+            (Some(x), Some(y)) => self.image[y * self.width + x],
+            _ => sampler.border_color,
+        }
+    }
+}
+
+/// Filter applied when sampling a texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+    /// Take the texel closest to the sampled position
+    Nearest,
+    /// Linearly interpolate between the four texels closest to the sampled position
+    Bilinear,
+}
+
+/// A cube map of generic type `T`, which is sampled by direction
+///
+/// The faces are oriented as in OpenGL, such that the usual skybox images can be used
+/// without flipping them. Faces are sampled by texture coordinates and therefore do not
+/// need to have the same size.
+#[derive(Debug, Clone)]
+pub struct CubeMap<T>
+where
+    T: Copy + Default,
+{
+    /// Faces in the direction of +x, -x, +y, -y, +z, and -z
+    pub faces: [Texture<T>; 6],
+}
+
+impl CubeMap<Float3> {
+    /// Load a color cube map from six PNG files given in the order +x, -x, +y, -y, +z,
+    /// and -z (right, left, top, bottom, back, and front as seen from the default
+    /// camera looking down the negative z-axis)
+    pub fn from_pngs(paths: [&str; 6]) -> Result<CubeMap<Float3>, TextureError> {
+        let [px, nx, py, ny, pz, nz] = paths.map(Texture::<Float3>::from_png);
+
+        Ok(CubeMap::new([px?, nx?, py?, ny?, pz?, nz?]))
+    }
+
+    /// Load a color cube map with faces of `size` x `size` texels from a PNG file with
+    /// an equirectangular projection
+    ///
+    /// See [from_equirectangular](CubeMap::from_equirectangular) for the orientation of
+    /// the image.
+    pub fn from_equirectangular_png(
+        path: &str,
+        size: usize,
+    ) -> Result<CubeMap<Float3>, TextureError> {
+        Ok(CubeMap::from_equirectangular(
+            &Texture::<Float3>::from_png(path)?,
+            size,
+        ))
+    }
+}
+
+impl<T> CubeMap<T>
+where
+    T: Copy + Default,
+{
+    /// Create a new cube map from its faces given in the order +x, -x, +y, -y, +z, and -z
+    pub fn new(faces: [Texture<T>; 6]) -> CubeMap<T> {
+        CubeMap { faces }
+    }
+
+    /// Face and texture coordinates on this face in the given direction
+    fn face_coordinates(direction: Float3) -> (usize, Float2) {
+        let (x, y, z) = (direction.x, direction.y, direction.z);
+        let (abs_x, abs_y, abs_z) = (x.abs(), y.abs(), z.abs());
+
+        // Major axis and coordinates with t running from the top to the bottom row
+        let (face, s, t, major) = if abs_x >= abs_y && abs_x >= abs_z {
+            if x > 0.0 {
+                (0, -z, -y, abs_x)
+            } else {
+                (1, z, -y, abs_x)
+            }
+        } else if abs_y >= abs_z {
+            if y > 0.0 {
+                (2, x, z, abs_y)
+            } else {
+                (3, x, -z, abs_y)
+            }
+        } else if z > 0.0 {
+            (4, x, -y, abs_z)
+        } else {
+            (5, -x, -y, abs_z)
+        };
+
+        (
+            face,
+            Float2::new((s / major + 1.0) * 0.5, (1.0 - t / major) * 0.5),
+        )
+    }
+
+    /// Direction through the given texture coordinates of a face, which is the inverse
+    /// of the mapping used for sampling
+    ///
+    /// The direction is not normalized.
+    pub fn face_direction(face: usize, texture_coord: Float2) -> Float3 {
+        let s = 2.0 * texture_coord.x - 1.0;
+        let t = 1.0 - 2.0 * texture_coord.y;
+
+        match face {
+            0 => Float3::new(1.0, -t, -s),
+            1 => Float3::new(-1.0, -t, s),
+            2 => Float3::new(s, 1.0, t),
+            3 => Float3::new(s, -1.0, -t),
+            4 => Float3::new(s, -t, 1.0),
+            5 => Float3::new(-s, -t, -1.0),
+            _ => panic!("Cube maps have six faces, got face {face}"),
+        }
+    }
+}
+
+impl<T> CubeMap<T>
+where
+    T: Copy + Default + Add<Output = T> + Mul<f32, Output = T>,
+{
+    /// Convert an image with an equirectangular projection to a cube map with faces of
+    /// `size` x `size` texels
+    ///
+    /// Longitude is mapped linearly to u, with the center of the image looking down the
+    /// negative z-axis and u increasing towards +x. Latitude is mapped linearly to v,
+    /// with the top row looking up (+y).
+    pub fn from_equirectangular(texture: &Texture<T>, size: usize) -> CubeMap<T> {
+        let sampler = Sampler::new(
+            FilterMode::Bilinear,
+            WrapMode::Repeat,
+            WrapMode::ClampToEdge,
+            T::default(),
+        );
+
+        let faces = std::array::from_fn(|face| {
+            let mut image = Vec::with_capacity(size * size);
+            for y in 0..size {
+                for x in 0..size {
+                    let texture_coord = Float2::new(
+                        (x as f32 + 0.5) / size as f32,
+                        1.0 - (y as f32 + 0.5) / size as f32,
+                    );
+                    let direction = Self::face_direction(face, texture_coord).normalized();
+                    let longitude = direction.x.atan2(-direction.z);
+                    let latitude = direction.y.clamp(-1.0, 1.0).asin();
+                    image.push(texture.sample_with(
+                        &sampler,
+                        Float2::new(
+                            0.5 + longitude / (2.0 * std::f32::consts::PI),
+                            0.5 + latitude / std::f32::consts::PI,
+                        ),
+                    ));
+                }
+            }
+
+            Texture {
+                width: size,
+                height: size,
+                image,
+                mipmaps: Vec::new(),
+            }
+        });
+
+        CubeMap::new(faces)
+    }
+
+    /// Sample in the given direction with bilinear filtering
+    ///
+    /// The direction does not need to be normalized.
+    pub fn sample(&self, direction: Float3) -> T {
+        self.sample_with(&Self::edge_sampler(), direction)
+    }
+
+    /// Sample in the given direction at a fractional level of detail with trilinear
+    /// filtering
+    ///
+    /// Level 0 are the faces themselves. Levels beyond the
+    /// [mip chains](CubeMap::generate_mipmaps) of the faces are clamped.
+    pub fn sample_lod(&self, direction: Float3, lod: f32) -> T {
+        let (face, texture_coord) = Self::face_coordinates(direction);
+
+        self.faces[face].sample_level(&Self::edge_sampler(), texture_coord, lod)
+    }
+
+    /// Generate the mip chains of all faces
+    pub fn generate_mipmaps(&mut self, filter: MipmapFilter) {
+        for face in self.faces.iter_mut() {
+            face.generate_mipmaps(filter);
+        }
+    }
+
+    /// Trilinear sampler which does not filter across the edges of a face
+    fn edge_sampler() -> Sampler<T> {
+        Sampler::new(
+            FilterMode::Bilinear,
+            WrapMode::ClampToEdge,
+            WrapMode::ClampToEdge,
+            T::default(),
+        )
+    }
+
+    /// Sample in the given direction with the given sampler
+    ///
+    /// Filtering does not cross the edges of the faces, so samplers should clamp to the
+    /// edges to avoid visible seams.
+    pub fn sample_with(&self, sampler: &Sampler<T>, direction: Float3) -> T {
+        let (face, texture_coord) = Self::face_coordinates(direction);
+
+        self.faces[face].sample_with(sampler, texture_coord)
+    }
+}
+
+/// Selection of the mipmap level when sampling with derivatives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MipmapMode {
+    /// Always sample the texture itself
+    None,
+    /// Sample the level closest to the footprint of the pixel
+    Nearest,
+    /// Linearly interpolate between the two closest levels (trilinear filtering when
+    /// combined with [FilterMode::Bilinear])
+    Linear,
+}
+
+/// Filter used to downsample the levels of a mip chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MipmapFilter {
+    /// Average of the texels covered by the footprint
+    Box,
+    /// Kaiser-windowed sinc, sharper than the box filter at the cost of slight ringing
+    Kaiser,
+}
+
+/// Radius of the Kaiser window in texels of the downsampled level
+const KAISER_RADIUS: f32 = 3.0;
+/// Shape parameter of the Kaiser window, larger values give a narrower window
+const KAISER_ALPHA: f32 = 4.0;
+
+impl MipmapFilter {
+    /// Normalized weights of the source texels contributing to each destination texel
+    /// when resampling `source` texels to `destination` texels
+    fn weights(&self, source: usize, destination: usize) -> Vec<Vec<(usize, f32)>> {
+        let scale = source as f32 / destination as f32;
+
+        (0..destination)
+            .map(|j| {
+                let (start, end) = (j as f32 * scale, (j + 1) as f32 * scale);
+                let center = (start + end) / 2.0;
+                let radius = match self {
+                    MipmapFilter::Box => scale / 2.0,
+                    MipmapFilter::Kaiser => KAISER_RADIUS * scale,
+                };
+
+                let first = (center - radius).floor() as i64;
+                let last = (center + radius).ceil() as i64;
+                let mut taps = (first..last)
+                    .map(|i| {
+                        let weight = match self {
+                            MipmapFilter::Box => {
+                                (end.min(i as f32 + 1.0) - start.max(i as f32)).max(0.0)
+                            }
+                            MipmapFilter::Kaiser => kaiser((i as f32 + 0.5 - center) / scale),
+                        };
+                        (i.clamp(0, source as i64 - 1) as usize, weight)
+                    })
+                    .filter(|&(_, weight)| weight != 0.0)
+                    .collect::<Vec<_>>();
+
+                let total = taps.iter().map(|&(_, weight)| weight).sum::<f32>();
+                for (_, weight) in &mut taps {
+                    *weight /= total;
+                }
+                taps
+            })
+            .collect()
+    }
+}
+
+/// Kaiser-windowed sinc at distance `x` in texels of the downsampled level
+fn kaiser(x: f32) -> f32 {
+    if x.abs() >= KAISER_RADIUS {
+        return 0.0;
+    }
+
+    let sinc = if x == 0.0 {
+        1.0
+    } else {
+        (std::f32::consts::PI * x).sin() / (std::f32::consts::PI * x)
+    };
+    let t = x / KAISER_RADIUS;
+    sinc * bessel_i0(KAISER_ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(KAISER_ALPHA)
+}
+
+/// Modified Bessel function of the first kind of order zero
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > 1e-7 * sum {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+/// Texture coordinates of a pixel with their screen-space derivatives
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextureCoordinates {
+    /// Texture coordinates (u, v)
+    pub uv: Float2,
+    /// Change of the texture coordinates to the next pixel in x-direction
+    pub duv_dx: Float2,
+    /// Change of the texture coordinates to the next pixel in y-direction
+    pub duv_dy: Float2,
+}
+
+impl TextureCoordinates {
+    /// Create new texture coordinates with derivatives
+    pub fn new(uv: Float2, duv_dx: Float2, duv_dy: Float2) -> Self {
+        Self { uv, duv_dx, duv_dy }
+    }
+}
+
+impl From<Float2> for TextureCoordinates {
+    /// Texture coordinates without derivatives, which always sample the most detailed
+    /// mipmap level
+    fn from(uv: Float2) -> Self {
+        Self::new(uv, Float2::zeros(), Float2::zeros())
+    }
+}
+
+/// Handling of texture coordinates outside of [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Tile the texture
+    Repeat,
+    /// Tile the texture, mirroring every other tile
+    MirroredRepeat,
+    /// Extend the texels at the edges of the texture
+    ClampToEdge,
+    /// Use the sampler's [border color](Sampler::border_color) outside of the texture
+    ClampToBorder,
+}
+
+impl WrapMode {
+    /// Map a texel index to an index in [0, size) or `None` if the border is sampled
+    fn wrap(&self, i: i64, size: usize) -> Option<usize> {
+        let size = size as i64;
+        if size == 0 {
+            return None;
+        }
+
+        match self {
+            WrapMode::Repeat => Some(i.rem_euclid(size) as usize),
+            WrapMode::MirroredRepeat => {
+                let i = i.rem_euclid(2 * size);
+                Some(if i < size { i } else { 2 * size - 1 - i } as usize)
+            }
+            WrapMode::ClampToEdge => Some(i.clamp(0, size - 1) as usize),
+            WrapMode::ClampToBorder => (0..size).contains(&i).then_some(i as usize),
+        }
+    }
+}
+
+/// Description of how a texture is sampled
+#[derive(Debug, Clone, Copy)]
+pub struct Sampler<T> {
+    /// Filter applied to the texels
+    pub filter: FilterMode,
+    /// Wrap mode of the horizontal texture coordinate u
+    pub wrap_u: WrapMode,
+    /// Wrap mode of the vertical texture coordinate v
+    pub wrap_v: WrapMode,
+    /// Color outside of the texture for [WrapMode::ClampToBorder]
+    pub border_color: T,
+    /// Selection of the mipmap level
+    pub mipmap_mode: MipmapMode,
+    /// Maximum number of samples along the footprint of a pixel, 1 disables
+    /// anisotropic filtering
+    pub max_anisotropy: usize,
+}
+
+impl<T> Sampler<T> {
+    /// Create a new sampler with linear mipmap selection and without anisotropic
+    /// filtering
+    pub fn new(filter: FilterMode, wrap_u: WrapMode, wrap_v: WrapMode, border_color: T) -> Self {
+        Self {
+            filter,
+            wrap_u,
+            wrap_v,
+            border_color,
+            mipmap_mode: MipmapMode::Linear,
+            max_anisotropy: 1,
+        }
+    }
+}
+
+impl Sampler<Float3> {
+    /// The same sampler for textures with alpha channel, whose border color is opaque
+    pub fn with_alpha(&self) -> Sampler<Float4> {
+        let border_color = self.border_color;
+
+        Sampler {
+            filter: self.filter,
+            wrap_u: self.wrap_u,
+            wrap_v: self.wrap_v,
+            border_color: Float4::new(border_color.x, border_color.y, border_color.z, 1.0),
+            mipmap_mode: self.mipmap_mode,
+            max_anisotropy: self.max_anisotropy,
+        }
+    }
+}
+
+impl<T> Default for Sampler<T>
+where
+    T: Default,
+{
+    /// Trilinear filtering with repeated texture coordinates
+    fn default() -> Self {
+        Self::new(
+            FilterMode::Bilinear,
+            WrapMode::Repeat,
+            WrapMode::Repeat,
+            T::default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use png::Encoder;
+    use std::io::BufWriter;
+
+    /// Encode an image to a PNG file in the temporary directory and return its path
+    fn write_png(
+        name: &str,
+        (width, height): (u32, u32),
+        color_type: ColorType,
+        bit_depth: BitDepth,
+        trns: Option<&[u8]>,
+        data: &[u8],
+    ) -> String {
+        let path = std::env::temp_dir().join(format!("rastr-{}-{name}.png", std::process::id()));
+        let file = File::create(&path).unwrap();
+
+        let mut encoder = Encoder::new(BufWriter::new(file), width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(bit_depth);
+        if let Some(trns) = trns {
+            encoder.set_trns(trns);
+        }
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+
+        path.to_str().unwrap().to_string()
+    }
+
+    /// Decode a PNG written by [write_png] and remove the file
+    fn decode(path: String) -> Texture<Float4> {
+        let texture = read_png(&path);
+        std::fs::remove_file(&path).unwrap();
+        texture.unwrap()
+    }
+
+    #[test]
+    fn decode_grayscale() {
+        let path = write_png(
+            "grayscale",
+            (2, 1),
+            ColorType::Grayscale,
+            BitDepth::Eight,
+            None,
+            &[0, 255],
+        );
+        let texture = decode(path);
+
+        assert_eq!((texture.width, texture.height), (2, 1));
+        assert_eq!(
+            texture.image,
+            vec![
+                Float4::new(0.0, 0.0, 0.0, 1.0),
+                Float4::new(1.0, 1.0, 1.0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_sixteen_bits() {
+        let path = write_png(
+            "sixteen-bits",
+            (1, 1),
+            ColorType::Rgb,
+            BitDepth::Sixteen,
+            None,
+            &[0x80, 0x00, 0xff, 0xff, 0x00, 0x00],
+        );
+        let texture = decode(path);
+
+        assert_eq!(
+            texture.image,
+            vec![Float4::new(32768.0 / 65535.0, 1.0, 0.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn decode_transparent_color_key() {
+        // The tRNS chunk of RGB images holds the transparent color with 16 bits per channel
+        let path = write_png(
+            "transparency",
+            (2, 1),
+            ColorType::Rgb,
+            BitDepth::Eight,
+            Some(&[0, 255, 0, 0, 0, 0]),
+            &[255, 0, 0, 0, 255, 0],
+        );
+        let texture = decode(path);
+
+        assert_eq!(
+            texture.image,
+            vec![
+                Float4::new(1.0, 0.0, 0.0, 0.0),
+                Float4::new(0.0, 1.0, 0.0, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn wrap_out_of_range_indices() {
+        let wrap = |mode: WrapMode, indices: [i64; 5]| indices.map(|i| mode.wrap(i, 4));
+
+        assert_eq!(
+            wrap(WrapMode::Repeat, [-5, -1, 2, 4, 9]),
+            [Some(3), Some(3), Some(2), Some(0), Some(1)]
+        );
+        assert_eq!(
+            wrap(WrapMode::MirroredRepeat, [-2, -1, 4, 5, 8]),
+            [Some(1), Some(0), Some(3), Some(2), Some(0)]
+        );
+        assert_eq!(
+            wrap(WrapMode::ClampToEdge, [-3, -1, 2, 4, 7]),
+            [Some(0), Some(0), Some(2), Some(3), Some(3)]
+        );
+        assert_eq!(
+            wrap(WrapMode::ClampToBorder, [-3, -1, 2, 4, 7]),
+            [None, None, Some(2), None, None]
+        );
+    }
+
+    #[test]
+    fn mipmap_weights_are_normalized_for_odd_sizes() {
+        for filter in [MipmapFilter::Box, MipmapFilter::Kaiser] {
+            for (source, destination) in [(3, 1), (5, 2), (7, 3), (9, 4)] {
+                let weights = filter.weights(source, destination);
+                assert_eq!(weights.len(), destination);
+
+                for taps in weights {
+                    let total = taps.iter().map(|&(_, weight)| weight).sum::<f32>();
+                    assert!(
+                        (total - 1.0).abs() < 1e-5,
+                        "{filter:?} {source}->{destination}"
+                    );
+                    assert!(taps.iter().all(|&(i, _)| i < source));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn box_weights_cover_odd_footprints() {
+        let weights = MipmapFilter::Box.weights(3, 1);
+        let third = 1.0 / 3.0;
+
+        assert_eq!(weights, vec![vec![(0, third), (1, third), (2, third)]]);
+    }
+
+    #[test]
+    fn face_coordinates_invert_face_direction() {
+        let coords = [0.1, 0.3, 0.5, 0.7, 0.9];
+        for face in 0..6 {
+            for u in coords {
+                for v in coords {
+                    let direction = CubeMap::<Float3>::face_direction(face, Float2::new(u, v));
+                    let (actual_face, uv) = CubeMap::<Float3>::face_coordinates(direction);
+
+                    assert_eq!(actual_face, face);
+                    assert!((uv.x - u).abs() < 1e-5 && (uv.y - v).abs() < 1e-5);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn face_centers_point_along_axes() {
+        let axes = [
+            Float3::new(1.0, 0.0, 0.0),
+            Float3::new(-1.0, 0.0, 0.0),
+            Float3::new(0.0, 1.0, 0.0),
+            Float3::new(0.0, -1.0, 0.0),
+            Float3::new(0.0, 0.0, 1.0),
+            Float3::new(0.0, 0.0, -1.0),
+        ];
+        for (face, axis) in axes.into_iter().enumerate() {
+            let direction = CubeMap::<Float3>::face_direction(face, Float2::new(0.5, 0.5));
+            assert_eq!(direction, axis);
+        }
+    }
+}